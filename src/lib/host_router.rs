@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+// Picks the router for a Host header before any path lookup happens. Every host gets its own
+// router R (usually a RoutingTable or a SharedRouter), so every host also has its own fallback.
+// Exact hosts win over "*.example.com" wildcards, longer wildcard suffixes win over shorter ones,
+// and the default router catches every host that matched nothing
+pub struct HostRouter<R> {
+    exact: HashMap<String, R>,
+    wildcard: Vec<(String, R)>,
+    default: Option<R>,
+}
+
+#[derive(Debug)]
+pub struct HostMatch<'h, R> {
+    pub router: &'h R,
+    pub host: String,
+    pub subdomain: Option<String>,
+}
+
+impl<R> HostRouter<R> {
+
+    pub fn new() -> Self {
+        HostRouter { exact: HashMap::new(), wildcard: Vec::new(), default: None }
+    }
+
+    pub fn add_host(self: &mut Self, pattern: &str, router: R) -> () {
+        let pattern = normalize_host(pattern);
+        match pattern.strip_prefix('*') {
+            Some(suffix) if suffix.starts_with('.') && suffix.len() > 1 => {
+                if self.wildcard.iter().any(|(existing, _)| existing == suffix) { panic!("Host {} is already routed", pattern) }
+                let position = self.wildcard.iter().position(|(existing, _)| existing.len() < suffix.len()).unwrap_or(self.wildcard.len());
+                self.wildcard.insert(position, (suffix.to_string(), router));
+            }
+            Some(_) => panic!("Wildcard hosts must look like *.example.com, got {}", pattern),
+            None => {
+                if self.exact.contains_key(&pattern) { panic!("Host {} is already routed", pattern) }
+                self.exact.insert(pattern, router);
+            }
+        }
+    }
+
+    pub fn set_default(self: &mut Self, router: R) -> () {
+        self.default = Some(router);
+    }
+
+    pub fn route(self: &Self, host: &str) -> Option<HostMatch<R>> {
+        let host = normalize_host(host);
+        if let Some(router) = self.exact.get(&host) {
+            return Some(HostMatch { router, host, subdomain: None })
+        }
+        for (suffix, router) in &self.wildcard {
+            if host.len() > suffix.len() && host.ends_with(suffix.as_str()) {
+                let subdomain = Some(host[..host.len()-suffix.len()].to_string());
+                return Some(HostMatch { router, host, subdomain })
+            }
+        }
+        self.default.as_ref().map(|router| HostMatch { router, host, subdomain: None })
+    }
+}
+
+// Hostnames are case-insensitive and the port never takes part in host routing:
+// "Example.COM.:8080" and "example.com" are the same host, "[::1]:8080" becomes "[::1]"
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim();
+    let without_port = match (host.starts_with('['), host.find(']'), host.rfind(':')) {
+        (true, Some(end), _) => &host[..=end],
+        (false, _, Some(colon)) if host[colon+1..].bytes().all(|b| b.is_ascii_digit()) => &host[..colon],
+        _ => host,
+    };
+    without_port.trim_end_matches('.').to_ascii_lowercase()
+}
+
+mod test {
+
+    use super::{HostRouter, normalize_host};
+    use super::super::routing_table::RoutingTable;
+
+    const LOCALHOST_FALLBACK: &i32 = &14;
+    const REMOTE_FALLBACK: &i32 = &15;
+    const TENANT_FALLBACK: &i32 = &16;
+    const TENANT_SIGNIN: &i32 = &17;
+    const DEFAULT_FALLBACK: &i32 = &18;
+
+    #[test]
+    fn host_normalization() {
+        assert_eq!(normalize_host("LocalHost:10099"), "localhost");
+        assert_eq!(normalize_host("Example.COM."), "example.com");
+        assert_eq!(normalize_host("example.com:"), "example.com");
+        assert_eq!(normalize_host("[::1]:8080"), "[::1]");
+        assert_eq!(normalize_host("[::1]"), "[::1]");
+    }
+
+    #[test]
+    fn wildcard_subdomains() {
+        let mut tenant = RoutingTable::new(TENANT_FALLBACK);
+        tenant.register(TENANT_SIGNIN, &["sign-in"]);
+        let mut hosts = HostRouter::new();
+        hosts.add_host("localhost", RoutingTable::new(LOCALHOST_FALLBACK));
+        hosts.add_host("*.remote.org", RoutingTable::new(REMOTE_FALLBACK));
+        hosts.add_host("*.Tenants.Remote.org", tenant);
+
+        let found = hosts.route("LOCALHOST:10099").unwrap();
+        assert_eq!(found.router.lookup(&["DNE"]).unwrap().val(), LOCALHOST_FALLBACK);
+        assert_eq!(found.subdomain, None);
+
+        let found = hosts.route("Acme.tenants.remote.org:443").unwrap();
+        assert_eq!(found.router.lookup(&["sign-in"]).unwrap().val(), TENANT_SIGNIN);
+        assert_eq!(found.host, "acme.tenants.remote.org");
+        assert_eq!(found.subdomain.as_deref(), Some("acme"));
+
+        let found = hosts.route("a.b.remote.org").unwrap();
+        assert_eq!(found.router.lookup(&["sign-in"]).unwrap().val(), REMOTE_FALLBACK);
+        assert_eq!(found.subdomain.as_deref(), Some("a.b"));
+
+        assert!(hosts.route("remote.org").is_none());
+        assert!(hosts.route("elsewhere.net").is_none());
+        hosts.set_default(RoutingTable::new(DEFAULT_FALLBACK));
+        assert_eq!(hosts.route("remote.org").unwrap().router.lookup::<&str>(&[]).unwrap().val(), DEFAULT_FALLBACK);
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_host_panic() {
+        let mut hosts = HostRouter::new();
+        hosts.add_host("*.remote.org", RoutingTable::new(REMOTE_FALLBACK));
+        hosts.add_host("*.REMOTE.org:80", RoutingTable::new(REMOTE_FALLBACK));
+    }
+}
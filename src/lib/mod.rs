@@ -1,3 +1,6 @@
 pub mod parse_url;
 pub mod routing_table;
+pub mod shared_router;
+pub mod host_router;
+pub mod route_config;
 mod channel;
\ No newline at end of file
@@ -6,6 +6,8 @@ pub struct ParsedUrl<'a> {
     after_query: Option<&'a str>,
     decoded_segments: Vec<String>,
     decoded_queries: Vec<(String, Option<String>)>,
+    redirect: Option<String>,
+    slash_policy: SlashPolicy,
 }
 
 #[derive(Debug)]
@@ -25,7 +27,36 @@ pub enum DuplicateParamPolicy<'a> {
 #[derive(Debug)]
 pub struct DupParamError(String);
 
+// What an empty segment means, "/a/" ends in one and "//a" starts with one.
+// Strict: every empty segment is a key of its own, "/a/" and "/a" are different routes.
+// Ignore: empty segments are dropped, "/a/", "//a" and "/a" are the same route and "/" has no segments.
+// Redirect: like Ignore, but any path that had one gets a redirect to the path without them.
+// Collapse: runs of slashes count as one, except that a trailing slash still ends in an empty segment
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SlashPolicy {
+    Strict,
+    Ignore,
+    Redirect,
+    Collapse,
+}
+
+impl SlashPolicy {
+    // Whether an empty segment survives parsing, last tells whether it ends the path.
+    // A RoutingTable only accepts empty keys in the positions its policy keeps them in
+    pub fn keeps_empty(self: &Self, last: bool) -> bool {
+        match self {
+            SlashPolicy::Strict => true,
+            SlashPolicy::Ignore | SlashPolicy::Redirect => false,
+            SlashPolicy::Collapse => last,
+        }
+    }
+}
+
 impl<'a> ParsedUrl<'a> {
+    pub fn decoded_segments(self: &Self) -> &[String] {
+        &self.decoded_segments
+    }
+
     pub fn decoded_query_map(self: &'a Self, null_replacement: &'a str, dup_policy: DuplicateParamPolicy) -> Result<std::collections::HashMap<&'a str, String>, DupParamError> {
         let mut hashmap = std::collections::HashMap::<&'a str, String>::new();
         for (k, v) in &self.decoded_queries {
@@ -47,9 +78,32 @@ impl<'a> ParsedUrl<'a> {
         Ok(hashmap)
     }
 
+    // Where the server should send the client instead, only ever set under SlashPolicy::Redirect
+    pub fn redirect(self: &Self) -> Option<&str> {
+        self.redirect.as_deref()
+    }
+
+    pub fn slash_policy(self: &Self) -> SlashPolicy {
+        self.slash_policy
+    }
+
     pub fn parse_new<TDecoderError>(raw_url: &str, decoder: UrlComponentDecoder<TDecoderError>) -> Result<ParsedUrl, TDecoderError> {
+        ParsedUrl::parse_with_policy(raw_url, decoder, SlashPolicy::Strict)
+    }
+
+    pub fn parse_with_policy<TDecoderError>(raw_url: &str, decoder: UrlComponentDecoder<TDecoderError>, policy: SlashPolicy) -> Result<ParsedUrl, TDecoderError> {
         let (before_query, after_query) = split_at_first_delim(raw_url, "?");
-        let decoded_segments = (if before_query.len() == 0 { "" } else { &before_query[1..] }).split("/").map(decoder).collect::<Result<Vec<String>, TDecoderError>>()?;
+        let raw_segments = (if before_query.len() == 0 { "" } else { &before_query[1..] }).split("/").collect::<Vec<&str>>();
+        let kept_segments = keep_segments(&raw_segments, policy);
+        let redirect = match policy {
+            SlashPolicy::Redirect if before_query.len() > 0 => {
+                let canonical = concat("/", &kept_segments.join("/"), "");
+                if before_query == canonical { None }
+                else { Some(concat(&canonical, after_query.map_or("", |_| "?"), after_query.unwrap_or(""))) }
+            }
+            _ => None,
+        };
+        let decoded_segments = kept_segments.into_iter().map(decoder).collect::<Result<Vec<String>, TDecoderError>>()?;
         let decoded_queries = {
             if let Some(after_query_concrete) = after_query { after_query_concrete.split("&").map(|query_entry| query_decoder(query_entry, decoder)).collect::<Result<Vec<(String, Option<String>)>, TDecoderError>>()? }
             else { Vec::new() }
@@ -58,11 +112,20 @@ impl<'a> ParsedUrl<'a> {
             before_query, 
             after_query, 
             decoded_segments,
-            decoded_queries
+            decoded_queries,
+            redirect,
+            slash_policy: policy,
         })
     }
 }
 
+fn keep_segments<'a>(raw_segments: &[&'a str], policy: SlashPolicy) -> Vec<&'a str> {
+    let last = raw_segments.len() - 1;
+    raw_segments.iter().enumerate()
+        .filter(|(position, segment)| segment.len() > 0 || policy.keeps_empty(*position == last))
+        .map(|(_, segment)| *segment).collect()
+}
+
 fn query_decoder<TDecoderError>(input: &str, decoder: UrlComponentDecoder<TDecoderError>) -> Result<(String, Option<String>), TDecoderError> {
     let (pre_before, pre_after) = split_at_first_delim(input, "=");
     let before = decoder(pre_before)?;
@@ -149,6 +212,27 @@ mod test {
         assert_eq!(qm06.get("noval2"), Some(&s("null null null")));
     }
 
+    #[test]
+    fn slash_policies() {
+        let segments = |raw_url: &str, policy: SlashPolicy| ParsedUrl::parse_with_policy(raw_url, identity_decoder, policy).unwrap().decoded_segments;
+        assert_eq!(segments("/api/v4/", SlashPolicy::Strict), vec![s("api"), s("v4"), s("")]);
+        assert_eq!(segments("//a", SlashPolicy::Strict), vec![s(""), s("a")]);
+        assert_eq!(segments("/api//v4/", SlashPolicy::Ignore), vec![s("api"), s("v4")]);
+        assert_eq!(segments("/", SlashPolicy::Ignore), Vec::<String>::new());
+        assert_eq!(segments("/api//v4//", SlashPolicy::Collapse), vec![s("api"), s("v4"), s("")]);
+        assert_eq!(segments("//a", SlashPolicy::Collapse), vec![s("a")]);
+        assert_eq!(segments("/", SlashPolicy::Collapse), vec![s("")]);
+        assert_eq!(segments("/api//v4/", SlashPolicy::Redirect), vec![s("api"), s("v4")]);
+
+        let redirect = |raw_url: &str| ParsedUrl::parse_with_policy(raw_url, identity_decoder, SlashPolicy::Redirect).unwrap().redirect;
+        assert_eq!(redirect("/api//v4/?page=2&sort"), Some(s("/api/v4?page=2&sort")));
+        assert_eq!(redirect("//"), Some(s("/")));
+        assert_eq!(redirect("/api/v4?page=2"), None);
+        assert_eq!(redirect("/"), None);
+        assert_eq!(redirect(""), None);
+        assert_eq!(ParsedUrl::parse_with_policy("/api/", identity_decoder, SlashPolicy::Ignore).unwrap().redirect, None);
+    }
+
     #[test]
     fn test_split_at_first_delim() {
         let (before, after) = split_at_first_delim("a=b", "=");
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+use serde_json::Value;
+use super::routing_table::{OwnedRoutingTable, RegistrationError, Predicate, Constraint, OneOrMore};
+
+// Routes declared in JSON only name their handlers, the program supplies the handlers themselves:
+// {
+//     "fallback": "not_found",
+//     "routes": [
+//         { "handler": "sign_up", "path": [["GET", "POST"], "api", "v4", "sign-up"] },
+//         { "handler": "sign_in", "path": ["api", "v4", "sign-in"], "name": "auth.sign_in" },
+//         { "handler": "user", "path": ["users", "{id:u64}"], "method": "GET" }
+//     ]
+// }
+// A path element is a key, or an array of alternatives like par(). "name" and "method" are optional
+// and do what reg_named and reg_method do, a route can't have both
+pub struct HandlerRegistry<T> {
+    handlers: HashMap<String, Arc<T>>,
+    constraints: Vec<(String, Predicate)>,
+}
+
+// at is a JSON pointer to the offending value, e.g. /routes/2/path/0
+#[derive(Debug)]
+pub enum ConfigError<T: Debug + 'static> {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Shape { at: String, expected: &'static str },
+    UnknownHandler { at: String, name: String },
+    Registration { at: String, error: RegistrationError<'static, T> },
+}
+
+impl<T> HandlerRegistry<T> {
+
+    pub fn new() -> Self {
+        HandlerRegistry { handlers: HashMap::new(), constraints: Vec::new() }
+    }
+
+    pub fn register(self: &mut Self, name: &str, handler: impl Into<Arc<T>>) -> () {
+        if self.handlers.insert(name.to_string(), handler.into()).is_some() {
+            panic!("Handler {} is already registered", name)
+        }
+    }
+
+    // Custom segment constraints used by the config, see RoutingTable::add_constraint. Redefining one
+    // panics here like it would there, so that load_routes never does
+    pub fn add_constraint(self: &mut Self, kind: &str, predicate: Predicate) -> () {
+        if Constraint::builtin(kind).is_some() || self.constraints.iter().any(|(existing, _)| existing == kind) {
+            panic!("Constraint {{{}}} is already defined", kind)
+        }
+        self.constraints.push((kind.to_string(), predicate));
+    }
+}
+
+pub fn load_routes_file<T: Debug + 'static>(path: impl AsRef<Path>, registry: &HandlerRegistry<T>) -> Result<OwnedRoutingTable<T>, ConfigError<T>> {
+    load_routes(&std::fs::read_to_string(path).map_err(ConfigError::Io)?, registry)
+}
+
+pub fn load_routes<T: Debug + 'static>(json: &str, registry: &HandlerRegistry<T>) -> Result<OwnedRoutingTable<T>, ConfigError<T>> {
+    let config: Value = serde_json::from_str(json).map_err(ConfigError::Json)?;
+    let mut rt = OwnedRoutingTable::new(handler(registry, &config, "", "fallback")?);
+    for (kind, predicate) in &registry.constraints {
+        rt.add_constraint(kind, *predicate);
+    }
+    let routes = config.get("routes").and_then(Value::as_array).ok_or_else(|| shape("/routes", "an array of routes"))?;
+    for (index, route) in routes.iter().enumerate() {
+        let at = format!("/routes/{}", index);
+        let entity = handler(registry, route, &at, "handler")?;
+        let path = route.get("path").and_then(Value::as_array).ok_or_else(|| shape(&format!("{}/path", at), "an array of keys"))?;
+        let keys = path.iter().enumerate().map(|(position, key)| {
+            let at = format!("{}/path/{}", at, position);
+            match key {
+                Value::String(key) => Ok(vec![key.as_str()]),
+                Value::Array(alternatives) if alternatives.len() > 0 => alternatives.iter().map(|each_rt| each_rt.as_str().ok_or_else(|| shape(&at, "a key or an array of keys"))).collect(),
+                _ => Err(shape(&at, "a key or an array of keys")),
+            }
+        }).collect::<Result<Vec<Vec<&str>>, ConfigError<T>>>()?;
+        let one_or_more = keys.iter().map(|alternatives| match alternatives.len() {
+            1 => OneOrMore::One(alternatives[0]),
+            _ => OneOrMore::More(alternatives),
+        }).collect::<Vec<OneOrMore>>();
+        let registered = match (optional_str(route, &at, "name")?, optional_str(route, &at, "method")?) {
+            (Some(_), Some(_)) => return Err(shape(&at, "a route with either a name or a method, not both")),
+            (Some(name), None) => rt.try_reg_named(name, entity, &one_or_more),
+            (None, Some(method)) => rt.try_reg_method(method, entity, &one_or_more),
+            (None, None) => rt.try_reg_more(entity, &one_or_more),
+        };
+        registered.map_err(|error| ConfigError::Registration { at, error })?;
+    }
+    Ok(rt)
+}
+
+fn handler<T: Debug + 'static>(registry: &HandlerRegistry<T>, object: &Value, at: &str, field: &str) -> Result<Arc<T>, ConfigError<T>> {
+    let at = format!("{}/{}", at, field);
+    let name = object.get(field).and_then(Value::as_str).ok_or_else(|| shape(&at, "a handler name"))?;
+    registry.handlers.get(name).cloned().ok_or_else(|| ConfigError::UnknownHandler { at, name: name.to_string() })
+}
+
+fn optional_str<'v, T: Debug + 'static>(object: &'v Value, at: &str, field: &str) -> Result<Option<&'v str>, ConfigError<T>> {
+    match object.get(field) {
+        None => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(shape(&format!("{}/{}", at, field), "a string")),
+    }
+}
+
+fn shape<T: Debug + 'static>(at: &str, expected: &'static str) -> ConfigError<T> {
+    ConfigError::Shape { at: at.to_string(), expected }
+}
+
+impl<T: Debug + 'static> Display for ConfigError<T> {
+    fn fmt(self: &Self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Cannot read route config: {}", error),
+            ConfigError::Json(error) => write!(f, "Route config is not valid JSON: {}", error),
+            ConfigError::Shape { at, expected } => write!(f, "Expected {} at {}", expected, at),
+            ConfigError::UnknownHandler { at, name } => write!(f, "Unknown handler {} at {}", name, at),
+            ConfigError::Registration { at, error } => write!(f, "{} at {}", error, at),
+        }
+    }
+}
+
+impl<T: Debug + 'static> Error for ConfigError<T> {}
+
+mod test {
+
+    use super::{HandlerRegistry, ConfigError, load_routes};
+    use super::super::routing_table::{RegistrationErrorKind, MethodMatch};
+
+    fn registry() -> HandlerRegistry<i32> {
+        let mut registry = HandlerRegistry::new();
+        for (name, handler) in vec![("not_found", 14), ("sign_up", 15), ("sign_in", 16), ("user", 19), ("hex", 26)] {
+            registry.register(name, handler);
+        }
+        registry.add_constraint("hex", |key| key.starts_with("0x"));
+        registry
+    }
+
+    #[test]
+    #[should_panic]
+    fn builtin_constraint_panic() {
+        registry().add_constraint("u64", |key| key.len() > 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicate_constraint_panic() {
+        registry().add_constraint("hex", |key| key.len() > 0);
+    }
+
+    #[test]
+    fn load_from_json() {
+        let rt = load_routes(r#"{
+            "fallback": "not_found",
+            "routes": [
+                { "handler": "sign_up", "path": [["GET", "POST"], "api", "v4", "sign-up"] },
+                { "handler": "sign_in", "path": ["api", "v4", "sign-in"], "name": "auth.sign_in" },
+                { "handler": "user", "path": ["users", "{id:u64}"], "method": "GET" },
+                { "handler": "hex", "path": ["orders", "{id:hex}"] }
+            ]
+        }"#, &registry()).unwrap();
+
+        assert_eq!(*rt.lookup(&["POST", "api", "v4", "sign-up"]).unwrap().val(), 15);
+        assert_eq!(*rt.lookup(&["GET", "api", "v4", "sign-up"]).unwrap().val(), 15);
+        assert_eq!(*rt.lookup(&["api", "v4", "DNE"]).unwrap().val(), 14);
+        assert_eq!(*rt.lookup(&["orders", "0xff"]).unwrap().val(), 26);
+        assert_eq!(rt.url_for("auth.sign_in", &[]).unwrap(), "/api/v4/sign-in");
+        assert_eq!(rt.lookup_method("GET", &["users", "42"]).unwrap().1, MethodMatch::Handler(&19));
+    }
+
+    #[test]
+    fn precise_errors() {
+        let load = |routes: &str| load_routes(&format!(r#"{{ "fallback": "not_found", "routes": {} }}"#, routes), &registry()).unwrap_err();
+
+        let error = load(r#"[{ "handler": "sign_up", "path": ["a"] }, { "handler": "sign_out", "path": ["b"] }]"#);
+        assert!(matches!(&error, ConfigError::UnknownHandler { at, name } if at == "/routes/1/handler" && name == "sign_out"));
+        assert_eq!(error.to_string(), "Unknown handler sign_out at /routes/1/handler");
+
+        let error = load(r#"[{ "handler": "sign_up", "path": ["POST", "sign-up"] }, { "handler": "sign_in", "path": [["GET", "POST"], "sign-up"] }]"#);
+        assert!(matches!(&error, ConfigError::Registration { at, error } if at == "/routes/1" && matches!(error.kind, RegistrationErrorKind::Duplicate(_))));
+        assert_eq!(error.to_string(), "Double registration error: /POST/sign-up is already registered to 15 (expanded from #0 = POST) at /routes/1");
+
+        assert!(matches!(load(r#"[{ "handler": "sign_up", "path": ["a", 4] }]"#), ConfigError::Shape { at, .. } if at == "/routes/0/path/1"));
+        assert!(matches!(load(r#"[{ "handler": "sign_up", "path": ["a"], "name": "x", "method": "GET" }]"#), ConfigError::Shape { at, .. } if at == "/routes/0"));
+        assert!(matches!(load(r#"{}"#), ConfigError::Shape { at, .. } if at == "/routes"));
+        assert!(matches!(load(r#"["#), ConfigError::Json(_)));
+    }
+}
@@ -1,145 +1,1231 @@
 #![allow(incomplete_features)]
 //#![feature(unsized_locals, unsized_fn_params)]
 
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::fmt::Debug;
-use std::iter::FromIterator;
+use std::error::Error;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::ops::Deref;
+use std::sync::Arc;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use super::parse_url::{ParsedUrl, SlashPolicy};
+
+mod radix;
+mod introspect;
+mod mount;
+mod frozen;
+mod stats;
+mod diff;
+mod index_test;
+#[macro_use]
+mod declare;
+pub use radix::RadixRoutingTable;
+pub use frozen::FrozenRoutingTable;
+pub use introspect::{RouteEntry, Routes};
+pub use mount::Scope;
+pub use stats::NodeStats;
+pub use diff::{RouteDiff, RouteChange};
+pub use declare::{split_route_path, has_duplicate_routes};
 
 #[derive(Debug)]
 pub struct RoutingTable<'a, T: Debug> {
-    map: HashMap<&'a str, RoutingTable<'a, T>>,
-    data: &'a T,
+    map: HashMap<Cow<'a, str>, RoutingTable<'a, T>>,
+    typed: Vec<(Cow<'a, str>, Constraint<'a>, RoutingTable<'a, T>)>,
+    param: Option<(Cow<'a, str>, Box<RoutingTable<'a, T>>)>,
+    catch_all: Option<(Cow<'a, str>, Box<RoutingTable<'a, T>>)>,
+    data: Entity<'a, T>,
+    explicit: bool,
+    fallback: Option<Entity<'a, T>>,
+    methods: Vec<(Cow<'a, str>, Entity<'a, T>)>,
+    fan_outs: Vec<Vec<Cow<'a, str>>>,
     depth: usize,
+    constraints: HashMap<Cow<'a, str>, Predicate>,
+    names: HashMap<Cow<'a, str>, Vec<Vec<Cow<'a, str>>>>,
+    stats: NodeStats,
+    record_stats: bool,
+    slash_policy: SlashPolicy,
+}
+
+// Owns every key and shares every entity, so routes can come from a database or config file at runtime
+#[derive(Debug)]
+pub struct OwnedRoutingTable<T: Debug + 'static>(RoutingTable<'static, T>);
+
+pub enum Entity<'a, T> { Borrowed(&'a T), Shared(Arc<T>) }
+
+// S is whatever the keys were handed over as, params borrow their values straight from them
+#[derive(Debug)]
+pub struct RTLookupResult<'a, T: Debug, S: 'a = &'a str> {
+    val: &'a T,
+    depth: usize, 
+    keys_used: usize,
+    keep_going: &'a RoutingTable<'a, T>,
+    params: Vec<(&'a str, &'a str, ParamValue)>,
+    rest: &'a [S],
+    missed: Option<&'a str>,
+    match_kind: MatchKind,
+}
+
+// Exact: every key was used and the node was registered, for the path itself or for some method.
+// Prefix: a registered node was reached with keys left over.
+// Fallback: the data is inherited, from an implicit layer or from the root when keys are left over,
+// or it is the fallback of a registered node that was reached with keys left over
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MatchKind { Exact, Prefix, Fallback }
+
+// Allowed lists hold the registered methods in registration order, then HEAD (when GET is registered) and OPTIONS
+#[derive(Debug, PartialEq)]
+pub enum MethodMatch<'a, T: Debug> {
+    Handler(&'a T),
+    NotAllowed(Vec<&'a str>),
+    Options(Vec<&'a str>),
+    NotFound,
+}
+
+impl<'a, T: Debug, S> RTLookupResult<'a, T, S> {
+    pub fn val(self: &Self) -> &'a T {
+        self.val
+    }
+
+    pub fn depth(self: &Self) -> usize {
+        self.depth
+    }
+
+    pub fn keys_used(self: &Self) -> usize {
+        self.keys_used
+    }
+
+    pub fn keep_going(self: &Self) -> &'a RoutingTable<'a, T> {
+        self.keep_going
+    }
+
+    pub fn param(self: &Self, name: &str) -> Option<&'a str> {
+        self.params.iter().find(|(param_name, _, _)| *param_name == name).map(|(_, raw, _)| *raw)
+    }
+
+    pub fn typed(self: &Self, name: &str) -> Option<ParamValue> {
+        self.params.iter().find(|(param_name, _, _)| *param_name == name).map(|(_, _, value)| *value)
+    }
+
+    pub fn rest(self: &Self) -> &'a [S] {
+        self.rest
+    }
+
+    pub fn match_kind(self: &Self) -> MatchKind {
+        self.match_kind
+    }
+
+    // The first key no child took, None when every key was used
+    pub fn missed(self: &Self) -> Option<&'a str> {
+        self.missed
+    }
+
+    // Static keys next to the missed one that are closest to it, see RoutingTable::suggest
+    pub fn suggestions(self: &Self, limit: usize) -> Vec<&'a str> {
+        self.missed.map(|missed| self.keep_going.suggest(missed, limit)).unwrap_or_default()
+    }
+}
+
+impl MatchKind {
+    fn of<T: Debug>(rt: &RoutingTable<T>, keys_used: usize, keys_len: usize) -> Self {
+        let keys_left = keys_used < keys_len;
+        // The root's own data is the bottom fallback, it only counts as registered once it has methods
+        let registered = (rt.explicit && rt.depth > 0) || !rt.methods.is_empty();
+        if !registered || (keys_left && rt.depth == 0) { MatchKind::Fallback }
+        else if keys_left { MatchKind::Prefix }
+        else { MatchKind::Exact }
+    }
+
+    fn rank(self: &Self) -> usize {
+        match self {
+            MatchKind::Exact => 0,
+            MatchKind::Prefix => 1,
+            MatchKind::Fallback => 2,
+        }
+    }
+}
+
+pub type Predicate = fn(&str) -> bool;
+
+// Constraints are tried in declaration order of the variants below (custom predicates in the
+// order they were added to the table), after static keys and before the unconstrained ":name" param
+// and the "*name" catch-all. Lookup backtracks through all of them, see lookup_core
+#[derive(Debug, Clone)]
+pub enum Constraint<'a> { U64, I64, Uuid, Custom(Cow<'a, str>, Predicate), Slug }
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ParamValue { Str, U64(u64), I64(i64), Uuid(u128) }
+
+#[derive(Debug)]
+pub struct RegistrationError<'a, T: Debug> {
+    pub route: Vec<String>,
+    pub expansion: Vec<(usize, String)>,
+    pub kind: RegistrationErrorKind<'a, T>,
+}
+
+#[derive(Debug)]
+pub enum RegistrationErrorKind<'a, T: Debug> {
+    EmptyRoute,
+    Duplicate(Entity<'a, T>),
+    ConflictingNames(String, String),
+    CatchAllNotLast,
+    UnknownConstraint(String),
+    DuplicateName(String),
+    NoAlternatives(usize),
+    EmptySegment,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UrlForError {
+    UnknownName(String),
+    MissingParam(String),
+    NotAnAlternative(usize, String),
+    ConstraintFailed(String, String),
+}
+
+impl<'a, T: Debug> RegistrationError<'a, T> {
+    fn new(route: &[&str], expansion: &[(usize, &str)], kind: RegistrationErrorKind<'a, T>) -> Self {
+        RegistrationError {
+            route: route.iter().map(|key| key.to_string()).collect(),
+            expansion: expansion.iter().map(|(position, each_rt)| (*position, each_rt.to_string())).collect(),
+            kind,
+        }
+    }
+}
+
+impl<'a, T: Debug> Display for RegistrationError<'a, T> {
+    fn fmt(self: &Self, f: &mut Formatter) -> fmt::Result {
+        let route = self.route.join("/");
+        match &self.kind {
+            RegistrationErrorKind::EmptyRoute => write!(f, "Double registration error (empty route registration)")?,
+            RegistrationErrorKind::Duplicate(existing) => write!(f, "Double registration error: /{} is already registered to {:?}", route, existing)?,
+            RegistrationErrorKind::ConflictingNames(existing, name) => write!(f, "Conflicting parameter names ({} and {}) at /{}", existing, name, route)?,
+            RegistrationErrorKind::CatchAllNotLast => write!(f, "Catch-all segment must be the last segment of a route: /{}", route)?,
+            RegistrationErrorKind::UnknownConstraint(kind) => write!(f, "Unknown segment constraint {{{}}} in /{}", kind, route)?,
+            RegistrationErrorKind::DuplicateName(name) => write!(f, "Route name {} is already taken, cannot register /{} under it", name, route)?,
+            RegistrationErrorKind::EmptySegment => write!(f, "Empty segment in /{}, the table's slash policy never produces one there", route)?,
+            RegistrationErrorKind::NoAlternatives(position) => write!(f, "Segment #{} of the route has no alternatives, it expands to no route at all", position)?,
+        }
+        if self.expansion.len() > 0 {
+            let picked = self.expansion.iter().map(|(position, each_rt)| format!("#{} = {}", position, each_rt)).collect::<Vec<String>>();
+            write!(f, " (expanded from {})", picked.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T: Debug> Error for RegistrationError<'a, T> {}
+
+impl Display for UrlForError {
+    fn fmt(self: &Self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            UrlForError::UnknownName(name) => write!(f, "No route is registered under the name {}", name),
+            UrlForError::MissingParam(param) => write!(f, "Missing value for parameter {}", param),
+            UrlForError::NotAnAlternative(position, picked) => write!(f, "{} is not one of the alternatives at #{}", picked, position),
+            UrlForError::ConstraintFailed(param, value) => write!(f, "Value {} does not satisfy the constraint on parameter {}", value, param),
+        }
+    }
 }
 
-#[derive(Debug)]
-pub struct RTLookupResult<'a, T: Debug> {
-    val: &'a T,
-    depth: usize, 
-    keys_used: usize,
-    keep_going: &'a RoutingTable<'a, T>,
+impl Error for UrlForError {}
+
+#[derive(Copy, Clone)]
+pub enum OneOrMore<'a> { One(&'a str), More(&'a [&'a str]) }
+pub fn one(str: &str) -> OneOrMore { OneOrMore::One(str) }
+pub fn more<'b>(str_arr: &'b [&str]) -> OneOrMore<'b> { OneOrMore::More(&str_arr) }
+
+#[derive(Copy, Clone)]
+pub enum SerialOrParallel<'a> { Serial(&'a [&'a str]), Parallel(&'a [&'a str]) }
+pub fn ser<'b>(str_arr: &'b [&str]) -> SerialOrParallel<'b> { SerialOrParallel::Serial(str_arr) }
+pub fn par<'b>(str_arr: &'b [&str]) -> SerialOrParallel<'b> { SerialOrParallel::Parallel(str_arr) }
+
+impl<'a, T: Debug> RoutingTable<'a, T> {
+
+    pub fn new(root_data:&'a T) -> Self {
+        RoutingTable::new_core(Entity::Borrowed(root_data), true, 0)
+    }
+
+    fn new_core(root_data: Entity<'a, T>, explicit: bool, depth: usize) -> Self {
+        RoutingTable {
+            map: HashMap::new(),
+            typed: Vec::new(),
+            param: None,
+            catch_all: None,
+            data: root_data,
+            explicit,
+            fallback: None,
+            methods: Vec::new(),
+            fan_outs: Vec::new(),
+            depth,
+            constraints: HashMap::new(),
+            names: HashMap::new(),
+            stats: NodeStats::default(),
+            record_stats: false,
+            slash_policy: SlashPolicy::Strict,
+        }
+    }
+
+    pub fn add_constraint(self: &mut Self, kind: &'a str, predicate: Predicate) -> () {
+        self.add_constraint_core(Cow::Borrowed(kind), predicate)
+    }
+
+    fn add_constraint_core(self: &mut Self, kind: Cow<'a, str>, predicate: Predicate) -> () {
+        if Constraint::builtin(&kind).is_some() || self.constraints.contains_key(&kind) {
+            panic!("Constraint {{{}}} is already defined", kind)
+        }
+        self.constraints.insert(kind, predicate);
+    }
+
+    // The policy urls are parsed with before they are looked up here, empty keys can only be
+    // registered where it keeps empty segments. Strict unless set otherwise, like ParsedUrl::parse_new
+    pub fn set_slash_policy(self: &mut Self, policy: SlashPolicy) -> () {
+        if !self.empty_keys_kept(policy) {
+            panic!("Routes with empty segments that {:?} never produces are already registered", policy)
+        }
+        self.slash_policy = policy;
+    }
+
+    pub fn slash_policy(self: &Self) -> SlashPolicy {
+        self.slash_policy
+    }
+
+    // An empty key is the last segment of a route exactly when nothing was registered below it
+    fn empty_keys_kept(self: &Self, policy: SlashPolicy) -> bool {
+        self.map.iter().all(|(key, child_rt)| key.len() > 0 || policy.keeps_empty(child_rt.children().next().is_none()))
+            && self.children().all(|child_rt| child_rt.empty_keys_kept(policy))
+    }
+
+    pub fn register(self: &mut Self, entity: &'a T, route: &'a [&str]) -> () {
+        self.try_register(entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn reg_more(self: &mut Self, entity: &'a T, route: &[OneOrMore<'a>]) -> () {
+        self.try_reg_more(entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn reg_parallel(self: &mut Self, entity: &'a T, route: &[SerialOrParallel<'a>]) -> () {
+        self.try_reg_parallel(entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_register(self: &mut Self, entity: &'a T, route: &'a [&str]) -> Result<(), RegistrationError<'a, T>> {
+        self.try_reg_more_core(Entity::Borrowed(entity), &serial_to_one_or_more(route), Cow::Borrowed)
+    }
+
+    pub fn try_reg_more(self: &mut Self, entity: &'a T, route: &[OneOrMore<'a>]) -> Result<(), RegistrationError<'a, T>> {
+        self.try_reg_more_core(Entity::Borrowed(entity), route, Cow::Borrowed)
+    }
+
+    pub fn try_reg_parallel(self: &mut Self, entity: &'a T, route: &[SerialOrParallel<'a>]) -> Result<(), RegistrationError<'a, T>> {
+        self.try_reg_more_core(Entity::Borrowed(entity), &parallel_to_one_or_more(route), Cow::Borrowed)
+    }
+
+    pub fn reg_method(self: &mut Self, method: &'a str, entity: &'a T, route: &[OneOrMore<'a>]) -> () {
+        self.try_reg_method(method, entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_reg_method(self: &mut Self, method: &'a str, entity: &'a T, route: &[OneOrMore<'a>]) -> Result<(), RegistrationError<'a, T>> {
+        self.try_reg_core(Entity::Borrowed(entity), route, Some(method), Cow::Borrowed)
+    }
+
+    pub fn reg_named(self: &mut Self, name: &'a str, entity: &'a T, route: &[OneOrMore<'a>]) -> () {
+        self.try_reg_named(name, entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_reg_named(self: &mut Self, name: &'a str, entity: &'a T, route: &[OneOrMore<'a>]) -> Result<(), RegistrationError<'a, T>> {
+        self.try_reg_named_core(name, Entity::Borrowed(entity), route, Cow::Borrowed)
+    }
+
+    // Every lookup under prefix that doesn't reach a registered node gets entity instead of the data
+    // inherited from further up, e.g. a 404 handler for /api/v4/*. An empty prefix sets it for the root
+    pub fn set_fallback(self: &mut Self, prefix: &[&'a str], entity: &'a T) -> () {
+        self.try_set_fallback(prefix, entity).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_set_fallback(self: &mut Self, prefix: &[&'a str], entity: &'a T) -> Result<(), RegistrationError<'a, T>> {
+        self.try_set_fallback_core(prefix, Entity::Borrowed(entity), Cow::Borrowed)
+    }
+
+    pub fn url_for(self: &Self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlForError> {
+        self.url_for_alt(name, params, &[])
+    }
+
+    // picks are (position, alternative) like RegistrationError::expansion, every OneOrMore::More
+    // that isn't picked uses its first alternative. Catch-all values are split at "/"
+    pub fn url_for_alt(self: &Self, name: &str, params: &[(&str, &str)], picks: &[(usize, &str)]) -> Result<String, UrlForError> {
+        let template = self.names.get(name).ok_or_else(|| UrlForError::UnknownName(name.to_string()))?;
+        if let Some((position, picked)) = picks.iter().find(|(position, _)| *position >= template.len()) {
+            return Err(UrlForError::NotAnAlternative(*position, picked.to_string()))
+        }
+        let mut segments = Vec::new();
+        for (position, alternatives) in template.iter().enumerate() {
+            let key = match picks.iter().find(|(picked_position, _)| *picked_position == position) {
+                Some((_, picked)) => alternatives.iter().find(|each_rt| each_rt == picked).ok_or_else(|| UrlForError::NotAnAlternative(position, picked.to_string()))?,
+                None => &alternatives[0],
+            };
+            let value_of = |param: &str| params.iter().find(|(param_name, _)| *param_name == param).map(|(_, value)| *value).ok_or_else(|| UrlForError::MissingParam(param.to_string()));
+            match parse_segment(key) {
+                Segment::Literal(literal) => segments.push(encode_segment(literal)),
+                Segment::Typed(param, kind) => {
+                    let value = value_of(param)?;
+                    let constraint = Constraint::resolve(kind, &self.constraints).expect("constraint is checked before registration");
+                    if constraint.test(value).is_none() { return Err(UrlForError::ConstraintFailed(param.to_string(), value.to_string())) }
+                    segments.push(encode_segment(value));
+                }
+                Segment::Param(param) => segments.push(encode_segment(value_of(param)?)),
+                Segment::CatchAll(param) => segments.extend(value_of(param)?.split('/').map(encode_segment)),
+            }
+        }
+        Ok(format!("/{}", segments.join("/")))
+    }
+
+    pub fn unregister(self: &mut Self, route: &[&str]) -> Option<Entity<'a, T>> {
+        if route.len() == 0 { return None }
+        let removed = self.unregister_core(route)?;
+        self.prune_names();
+        Some(removed)
+    }
+
+    pub fn replace(self: &mut Self, route: &[&str], entity: &'a T) -> Option<Entity<'a, T>> {
+        self.replace_core(route, Entity::Borrowed(entity))
+    }
+
+    // Only touch what reg_method put there, the route itself and its other methods stay
+    pub fn unregister_method(self: &mut Self, method: &str, route: &[&str]) -> Option<Entity<'a, T>> {
+        self.unregister_method_core(method, route)
+    }
+
+    pub fn replace_method(self: &mut Self, method: &str, route: &[&str], entity: &'a T) -> Option<Entity<'a, T>> {
+        self.replace_method_core(method, route, Entity::Borrowed(entity))
+    }
+
+    pub fn remove_subtree(self: &mut Self, prefix: &[&str]) -> usize {
+        if prefix.len() == 0 {
+            let removed = self.children().map(|child| child.count_routes()).sum::<usize>();
+            self.map.clear();
+            self.typed.clear();
+            self.param = None;
+            self.catch_all = None;
+            self.names.clear();
+            return removed + std::mem::take(&mut self.methods).len()
+        }
+        let removed = self.remove_subtree_core(prefix);
+        self.prune_names();
+        removed
+    }
+
+    pub fn to_owned_table(self: &Self) -> OwnedRoutingTable<T> where T: Clone + 'static {
+        OwnedRoutingTable(self.to_owned_core(&mut HashMap::new()))
+    }
+
+    fn try_reg_more_core<'r>(self: &mut Self, entity: Entity<'a, T>, route: &[OneOrMore<'r>], to_key: fn(&'r str) -> Cow<'a, str>) -> Result<(), RegistrationError<'a, T>> {
+        self.try_reg_core(entity, route, None, to_key)
+    }
+
+    // The route may borrow for less than 'a, to_key decides how its keys end up stored in the table.
+    // Without a method the entity serves the node itself, with one it only serves that method there
+    fn try_reg_core<'r>(self: &mut Self, entity: Entity<'a, T>, route: &[OneOrMore<'r>], method: Option<&'r str>, to_key: fn(&'r str) -> Cow<'a, str>) -> Result<(), RegistrationError<'a, T>> {
+        if route.len() == 0 && method.is_none() {
+            return Err(RegistrationError::new(&[], &[], RegistrationErrorKind::EmptyRoute))
+        }
+        if let Some(position) = without_alternatives(route) {
+            return Err(RegistrationError::new(&[], &[], RegistrationErrorKind::NoAlternatives(position)))
+        }
+        // Every expansion is checked before anything is inserted, so a failed registration leaves the table untouched
+        let constraints = self.constraints.clone();
+        let expanded = expand(route);
+        for (index, (full_route, expansion)) in expanded.iter().enumerate() {
+            let error = |kind| RegistrationError::new(full_route, expansion, kind);
+            self.check_route(full_route, method, &constraints).map_err(error)?;
+            for (earlier_route, _) in &expanded[..index] {
+                if same_route(earlier_route, full_route).map_err(error)? {
+                    return Err(error(RegistrationErrorKind::Duplicate(entity.clone())))
+                }
+            }
+        }
+        for (full_route, _) in &expanded {
+            self.register_one_core(&entity, full_route, method, &constraints, to_key);
+        }
+        // Remembered for to_dot, every node a OneOrMore::More branched from gets the keys it branched into
+        for (full_route, expansion) in &expanded {
+            for (position, _) in expansion {
+                if let OneOrMore::More(more_rt) = route[*position] {
+                    let fan_out = more_rt.iter().map(|each_rt| to_key(each_rt)).collect();
+                    self.layer_mut(&full_route[..*position], &constraints, to_key).add_fan_out(fan_out);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn try_reg_named_core<'r>(self: &mut Self, name: &'r str, entity: Entity<'a, T>, route: &[OneOrMore<'r>], to_key: fn(&'r str) -> Cow<'a, str>) -> Result<(), RegistrationError<'a, T>> {
+        // url_for_alt falls back to the first alternative, so every segment needs one
+        if let Some(position) = without_alternatives(route) {
+            return Err(RegistrationError::new(&[], &[], RegistrationErrorKind::NoAlternatives(position)))
+        }
+        if self.names.contains_key(name) {
+            let (full_route, expansion) = expand(route).swap_remove(0);
+            return Err(RegistrationError::new(&full_route, &expansion, RegistrationErrorKind::DuplicateName(name.to_string())))
+        }
+        self.try_reg_more_core(entity, route, to_key)?;
+        let template = route.iter().map(|next_rt| match next_rt {
+            OneOrMore::One(one_rt) => vec![to_key(one_rt)],
+            OneOrMore::More(more_rt) => more_rt.iter().map(|each_rt| to_key(each_rt)).collect(),
+        }).collect();
+        self.names.insert(to_key(name), template);
+        Ok(())
+    }
+
+    fn try_set_fallback_core<'r>(self: &mut Self, prefix: &[&'r str], entity: Entity<'a, T>, to_key: fn(&'r str) -> Cow<'a, str>) -> Result<(), RegistrationError<'a, T>> {
+        let constraints = self.constraints.clone();
+        let error = |kind| RegistrationError::new(prefix, &[], kind);
+        self.check_path(prefix, &constraints, |found_rt| found_rt.fallback.as_ref()).map_err(error)?;
+        let node = self.layer_mut(prefix, &constraints, to_key);
+        node.fallback = Some(entity);
+        let data = if node.explicit { node.data.clone() } else { node.inheritable() };
+        node.set_data(data);
+        Ok(())
+    }
+
+    fn check_route(self: &Self, route: &[&str], method: Option<&str>, constraints: &HashMap<Cow<'a, str>, Predicate>) -> Result<(), RegistrationErrorKind<'a, T>> {
+        self.check_path(route, constraints, |found_rt| found_rt.registered(method))
+    }
+
+    // taken tells whether the node at the end of route already holds what is about to be put there
+    fn check_path(self: &Self, route: &[&str], constraints: &HashMap<Cow<'a, str>, Predicate>, taken: impl Fn(&Self) -> Option<&Entity<'a, T>>) -> Result<(), RegistrationErrorKind<'a, T>> {
+        for (position, key) in route.iter().enumerate() {
+            match parse_segment(key) {
+                Segment::Literal("") if !self.slash_policy.keeps_empty(position+1 == route.len()) => return Err(RegistrationErrorKind::EmptySegment),
+                Segment::CatchAll(_) if position+1 < route.len() => return Err(RegistrationErrorKind::CatchAllNotLast),
+                Segment::Typed(_, kind) if Constraint::resolve(kind, constraints).is_none() => return Err(RegistrationErrorKind::UnknownConstraint(kind.to_string())),
+                _ => {}
+            }
+        }
+        let mut current_rt = self;
+        for key in route {
+            match current_rt.child(key)? {
+                Some(found_rt) => current_rt = found_rt,
+                None => return Ok(()),
+            }
+        }
+        match taken(current_rt) {
+            Some(existing) => Err(RegistrationErrorKind::Duplicate(existing.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn register_one_core<'r>(self: &mut Self, entity: &Entity<'a, T>, route: &[&'r str], method: Option<&'r str>, constraints: &HashMap<Cow<'a, str>, Predicate>, to_key: fn(&'r str) -> Cow<'a, str>) -> () {
+        let node = self.layer_mut(route, constraints, to_key);
+        match method {
+            None => {
+                node.explicit = true;
+                node.set_data(entity.clone());
+            }
+            Some(method) => node.methods.push((to_key(method), entity.clone())),
+        }
+    }
+
+    // The node at route, creating the implicit layers that lead to it
+    fn layer_mut<'r>(self: &mut Self, route: &[&'r str], constraints: &HashMap<Cow<'a, str>, Predicate>, to_key: fn(&'r str) -> Cow<'a, str>) -> &mut RoutingTable<'a, T> {
+        let next_rt = match route.first() {
+            Some(next_rt) => *next_rt,
+            None => return self,
+        };
+        if self.child_mut(next_rt).is_none() {
+            let implicit_layer = RoutingTable::new_core(self.inheritable(), false, self.depth+1);
+            self.insert_child(next_rt, implicit_layer, constraints, to_key);
+        }
+        self.child_mut(next_rt).unwrap().layer_mut(&route[1..], constraints, to_key)
+    }
+
+    fn registered(self: &Self, method: Option<&str>) -> Option<&Entity<'a, T>> {
+        match method {
+            None if self.explicit => Some(&self.data),
+            None => None,
+            Some(method) => self.methods.iter().find(|(existing, _)| existing == method).map(|(_, entity)| entity),
+        }
+    }
+
+    fn child(self: &Self, key: &str) -> Result<Option<&RoutingTable<'a, T>>, RegistrationErrorKind<'a, T>> {
+        match parse_segment(key) {
+            Segment::Literal(literal) => Ok(self.map.get(literal)),
+            Segment::Typed(name, kind) => match self.typed.iter().find(|(_, existing, _)| existing.kind() == kind) {
+                Some((existing, _, _)) if existing != name => Err(RegistrationErrorKind::ConflictingNames(existing.to_string(), name.to_string())),
+                found => Ok(found.map(|(_, _, typed_rt)| typed_rt)),
+            }
+            Segment::Param(name) => match &self.param {
+                Some((existing, _)) if existing != name => Err(RegistrationErrorKind::ConflictingNames(existing.to_string(), name.to_string())),
+                found => Ok(found.as_ref().map(|(_, param_rt)| &**param_rt)),
+            }
+            Segment::CatchAll(name) => match &self.catch_all {
+                Some((existing, _)) if existing != name => Err(RegistrationErrorKind::ConflictingNames(existing.to_string(), name.to_string())),
+                found => Ok(found.as_ref().map(|(_, catch_all_rt)| &**catch_all_rt)),
+            }
+        }
+    }
+
+    fn child_mut(self: &mut Self, key: &str) -> Option<&mut RoutingTable<'a, T>> {
+        match parse_segment(key) {
+            Segment::Literal(literal) => self.map.get_mut(literal),
+            Segment::Typed(_, kind) => self.typed.iter_mut().find(|(_, existing, _)| existing.kind() == kind).map(|(_, _, typed_rt)| typed_rt),
+            Segment::Param(_) => self.param.as_mut().map(|(_, param_rt)| &mut **param_rt),
+            Segment::CatchAll(_) => self.catch_all.as_mut().map(|(_, catch_all_rt)| &mut **catch_all_rt),
+        }
+    }
+
+    fn remove_child(self: &mut Self, key: &str) -> Option<RoutingTable<'a, T>> {
+        match parse_segment(key) {
+            Segment::Literal(literal) => self.map.remove(literal),
+            Segment::Typed(_, kind) => {
+                let position = self.typed.iter().position(|(_, existing, _)| existing.kind() == kind)?;
+                Some(self.typed.remove(position).2)
+            }
+            Segment::Param(_) => self.param.take().map(|(_, param_rt)| *param_rt),
+            Segment::CatchAll(_) => self.catch_all.take().map(|(_, catch_all_rt)| *catch_all_rt),
+        }
+    }
+
+    // Unlike child_mut this also requires parameter names to match, so ":user" doesn't address ":id"
+    fn child_named_mut(self: &mut Self, key: &str) -> Option<&mut RoutingTable<'a, T>> {
+        match self.child(key) {
+            Ok(Some(_)) => self.child_mut(key),
+            _ => None,
+        }
+    }
+
+    fn children(self: &Self) -> impl Iterator<Item = &RoutingTable<'a, T>> {
+        self.map.values()
+            .chain(self.typed.iter().map(|(_, _, typed_rt)| typed_rt))
+            .chain(self.param.iter().map(|(_, param_rt)| &**param_rt))
+            .chain(self.catch_all.iter().map(|(_, catch_all_rt)| &**catch_all_rt))
+    }
+
+    fn children_mut(self: &mut Self) -> impl Iterator<Item = &mut RoutingTable<'a, T>> {
+        self.map.values_mut()
+            .chain(self.typed.iter_mut().map(|(_, _, typed_rt)| typed_rt))
+            .chain(self.param.iter_mut().map(|(_, param_rt)| &mut **param_rt))
+            .chain(self.catch_all.iter_mut().map(|(_, catch_all_rt)| &mut **catch_all_rt))
+    }
+
+    // Implicit layers carry their parent's data, or its fallback when it has one, so it has to
+    // follow whenever either changes. An implicit layer with a fallback of its own keeps that one
+    fn set_data(self: &mut Self, data: Entity<'a, T>) -> () {
+        self.data = data;
+        let inherited = self.inheritable();
+        for child in self.children_mut() { child.inherit(inherited.clone()) }
+    }
+
+    fn inherit(self: &mut Self, inherited: Entity<'a, T>) -> () {
+        if !self.explicit { self.set_data(self.fallback.clone().unwrap_or(inherited)) }
+    }
+
+    fn inheritable(self: &Self) -> Entity<'a, T> {
+        self.fallback.as_ref().unwrap_or(&self.data).clone()
+    }
+
+    fn add_fan_out(self: &mut Self, fan_out: Vec<Cow<'a, str>>) -> () {
+        if fan_out.len() > 1 && !self.fan_outs.contains(&fan_out) { self.fan_outs.push(fan_out) }
+    }
+
+    fn has_dynamic_children(self: &Self) -> bool {
+        self.typed.len() > 0 || self.param.is_some() || self.catch_all.is_some()
+    }
+
+    fn count_routes(self: &Self) -> usize {
+        self.children().map(|child| child.count_routes()).sum::<usize>() + self.methods.len() + if self.explicit { 1 } else { 0 }
+    }
+
+    // Drops the child at key if it is an implicit layer that no longer leads to any route
+    fn prune(self: &mut Self, key: &str) -> () {
+        if let Some(child) = self.child_mut(key) {
+            if !child.explicit && child.methods.is_empty() && child.fallback.is_none() && child.children().next().is_none() { self.remove_child(key); }
+        }
+    }
+
+    fn unregister_core(self: &mut Self, route: &[&str]) -> Option<Entity<'a, T>> {
+        let (next_rt, rest_rt) = (route[0], &route[1..]);
+        let inherited = self.inheritable();
+        let child = self.child_named_mut(next_rt)?;
+        let removed = if rest_rt.len() == 0 {
+            if !child.explicit { return None }
+            let removed = child.data.clone();
+            child.explicit = false;
+            child.inherit(inherited);
+            removed
+        }
+        else {
+            child.unregister_core(rest_rt)?
+        };
+        self.prune(next_rt);
+        Some(removed)
+    }
+
+    // A name only stays while every route it expanded to is still registered, so url_for never builds a dead URL
+    fn prune_names(self: &mut Self) -> () {
+        let names = std::mem::take(&mut self.names);
+        self.names = names.into_iter().filter(|(_, template)| self.registers_template(template)).collect();
+    }
+
+    fn registers_template(self: &Self, template: &[Vec<Cow<'a, str>>]) -> bool {
+        match template.split_first() {
+            Some((alternatives, rest)) => alternatives.iter().all(|each_rt| matches!(self.child(each_rt), Ok(Some(child)) if child.registers_template(rest))),
+            None => self.explicit,
+        }
+    }
+
+    fn unregister_method_core(self: &mut Self, method: &str, route: &[&str]) -> Option<Entity<'a, T>> {
+        if route.len() == 0 {
+            let position = self.methods.iter().position(|(existing, _)| existing == method)?;
+            return Some(self.methods.remove(position).1)
+        }
+        let removed = self.child_named_mut(route[0])?.unregister_method_core(method, &route[1..])?;
+        self.prune(route[0]);
+        Some(removed)
+    }
+
+    fn replace_method_core(self: &mut Self, method: &str, route: &[&str], entity: Entity<'a, T>) -> Option<Entity<'a, T>> {
+        if route.len() == 0 {
+            let (_, existing) = self.methods.iter_mut().find(|(existing, _)| existing == method)?;
+            return Some(std::mem::replace(existing, entity))
+        }
+        self.child_named_mut(route[0])?.replace_method_core(method, &route[1..], entity)
+    }
+
+    fn replace_core(self: &mut Self, route: &[&str], entity: Entity<'a, T>) -> Option<Entity<'a, T>> {
+        if route.len() == 0 {
+            if !self.explicit { return None }
+            let replaced = self.data.clone();
+            self.set_data(entity);
+            return Some(replaced)
+        }
+        self.child_named_mut(route[0])?.replace_core(&route[1..], entity)
+    }
+
+    fn remove_subtree_core(self: &mut Self, prefix: &[&str]) -> usize {
+        let (next_rt, rest_rt) = (prefix[0], &prefix[1..]);
+        let removed = if rest_rt.len() == 0 {
+            match self.child(next_rt) {
+                Ok(Some(_)) => self.remove_child(next_rt).map(|child| child.count_routes()).unwrap_or(0),
+                _ => 0,
+            }
+        }
+        else {
+            match self.child_named_mut(next_rt) {
+                Some(child) => child.remove_subtree_core(rest_rt),
+                None => 0,
+            }
+        };
+        self.prune(next_rt);
+        removed
+    }
+
+    fn insert_child<'r>(self: &mut Self, key: &'r str, child: RoutingTable<'a, T>, constraints: &HashMap<Cow<'a, str>, Predicate>, to_key: fn(&'r str) -> Cow<'a, str>) -> () {
+        match parse_segment(key) {
+            Segment::Literal(literal) => { self.map.insert(to_key(literal), child); }
+            Segment::Typed(name, kind) => {
+                let constraint = Constraint::resolve(kind, constraints).expect("constraint is checked before registration");
+                let position = self.typed.iter().position(|(_, existing, _)| existing.rank() > constraint.rank()).unwrap_or(self.typed.len());
+                self.typed.insert(position, (to_key(name), constraint, child));
+            }
+            Segment::Param(name) => { self.param = Some((to_key(name), Box::new(child))) }
+            Segment::CatchAll(name) => { self.catch_all = Some((to_key(name), Box::new(child))) }
+        }
+    }
+
+    // This node without its children, for the indexes (radix and frozen) that keep their own copy of
+    // every node they store. It answers stop_here and catch_all_here exactly like this node does
+    fn leaf(self: &Self) -> RoutingTable<'a, T> {
+        let mut leaf = RoutingTable::new_core(self.data.clone(), self.explicit, self.depth);
+        leaf.fallback = self.fallback.clone();
+        leaf.methods = self.methods.clone();
+        leaf.record_stats = self.record_stats;
+        leaf
+    }
+
+    // Entities are cloned once and then shared by every node that referred to the same entity
+    fn to_owned_core(self: &Self, shared: &mut HashMap<*const T, Arc<T>>) -> RoutingTable<'static, T> where T: Clone + 'static {
+        RoutingTable {
+            map: self.map.iter().map(|(key, child)| (to_owned_key(key), child.to_owned_core(shared))).collect(),
+            typed: self.typed.iter().map(|(name, constraint, child)| (to_owned_key(name), constraint.to_owned_constraint(), child.to_owned_core(shared))).collect(),
+            param: self.param.as_ref().map(|(name, child)| (to_owned_key(name), Box::new(child.to_owned_core(shared)))),
+            catch_all: self.catch_all.as_ref().map(|(name, child)| (to_owned_key(name), Box::new(child.to_owned_core(shared)))),
+            data: self.data.to_shared(shared),
+            explicit: self.explicit,
+            fallback: self.fallback.as_ref().map(|fallback| fallback.to_shared(shared)),
+            methods: self.methods.iter().map(|(method, entity)| (to_owned_key(method), entity.to_shared(shared))).collect(),
+            fan_outs: self.fan_outs.iter().map(|fan_out| fan_out.iter().map(|key| to_owned_key(key)).collect()).collect(),
+            depth: self.depth,
+            constraints: self.constraints.iter().map(|(kind, predicate)| (to_owned_key(kind), *predicate)).collect(),
+            names: self.names.iter().map(|(name, template)| (to_owned_key(name), template.iter().map(|alternatives| alternatives.iter().map(|each_rt| to_owned_key(each_rt)).collect()).collect())).collect(),
+            stats: NodeStats::default(),
+            record_stats: self.record_stats,
+            slash_policy: self.slash_policy,
+        }
+    }
+    
+    // Keys are a slice of anything that derefs to str, e.g. the Strings of ParsedUrl::decoded_segments,
+    // so nothing has to be collected into a Vec<&str> first
+    pub fn lookup<'s, S: AsRef<str>>(self: &'s Self, keys: &'s [S]) -> Option<RTLookupResult<'s, T, S>> {
+        let found = self.lookup_core(keys, 0, &mut Vec::new())?;
+        self.record_hit(&found);
+        Some(found)
+    }
+
+    pub fn lookup_url<'s>(self: &'s Self, url: &'s ParsedUrl) -> Option<RTLookupResult<'s, T, String>> {
+        if url.slash_policy() != self.slash_policy {
+            panic!("Url parsed under {:?} looked up in a table under {:?}", url.slash_policy(), self.slash_policy)
+        }
+        self.lookup(url.decoded_segments())
+    }
+    
+    // Method registrations only answer when every key was used, a node without any of them
+    // serves every method with its own data as long as it was registered for the path itself
+    pub fn lookup_method<'s, S: AsRef<str>>(self: &'s Self, method: &str, keys: &'s [S]) -> Option<(RTLookupResult<'s, T, S>, MethodMatch<'s, T>)> {
+        let found = self.lookup(keys)?;
+        let rt = found.keep_going;
+        let method_match = if found.keys_used < keys.len() || (rt.methods.is_empty() && found.match_kind != MatchKind::Exact) {
+            MethodMatch::NotFound
+        }
+        else if rt.methods.is_empty() {
+            MethodMatch::Handler(found.val)
+        }
+        else if let Some(entity) = rt.registered(Some(method)).or_else(|| if method == "HEAD" { rt.registered(Some("GET")) } else { None }) {
+            MethodMatch::Handler(&**entity)
+        }
+        else {
+            let mut allowed = rt.methods.iter().map(|(method, _)| method.as_ref()).collect::<Vec<&str>>();
+            if allowed.contains(&"GET") && !allowed.contains(&"HEAD") { allowed.push("HEAD") }
+            if !allowed.contains(&"OPTIONS") { allowed.push("OPTIONS") }
+            if method == "OPTIONS" { MethodMatch::Options(allowed) } else { MethodMatch::NotAllowed(allowed) }
+        };
+        Some((found, method_match))
+    }
+
+    // Children are tried in priority order: the static key, then every typed param whose constraint
+    // accepts the key (in the order described at Constraint), then ":name", then "*name". Each one is
+    // searched to the end before the next one is tried, and a path ends where no child takes the next key.
+    // The first path that ends in an Exact match wins, otherwise the first Prefix, otherwise the first Fallback.
+    // params holds those of the path taken so far: each candidate pushes its own and they are truncated again
+    // before the next one, so a lookup ending in an Exact match allocates no more than the Vec it returns
+    fn lookup_core<'s, S: AsRef<str>>(self: &'s Self, keys: &'s [S], start: usize, params: &mut Vec<(&'s str, &'s str, ParamValue)>) -> Option<RTLookupResult<'s, T, S>> {
+        let key = match keys.get(start) {
+            Some(key) => key.as_ref(),
+            None => return Some(self.stop_here(keys, start, params)),
+        };
+        let mut best: Option<RTLookupResult<'s, T, S>> = None;
+        let candidates = self.map.get(key).map(|map| (map, None)).into_iter()
+            .chain(self.typed.iter().filter_map(|(name, constraint, typed_rt)| constraint.test(key).map(|value| (typed_rt, Some((&**name, key, value))))))
+            .chain(self.param.iter().map(|(name, param_rt)| (&**param_rt, Some((&**name, key, ParamValue::Str)))));
+        let params_len = params.len();
+        for (child, param) in candidates {
+            params.extend(param);
+            let found = child.lookup_core(keys, start+1, params)?;
+            if found.match_kind == MatchKind::Exact { return Some(found) }
+            params.truncate(params_len);
+            if best.as_ref().map_or(true, |best| found.match_kind.rank() < best.match_kind.rank()) { best = Some(found) }
+        }
+        if let Some((_, catch_all_rt)) = &self.catch_all {
+            let found = catch_all_rt.catch_all_here(keys, start, params);
+            if best.as_ref().map_or(true, |best| found.match_kind.rank() < best.match_kind.rank()) { best = Some(found) }
+        }
+        Some(best.unwrap_or_else(|| self.stop_here(keys, start, params)))
+    }
+
+    // The catch-all takes every key from start on, so the lookup ends in it without looking at them
+    fn catch_all_here<'s, S: AsRef<str>>(self: &'s Self, keys: &'s [S], start: usize, params: &mut Vec<(&'s str, &'s str, ParamValue)>) -> RTLookupResult<'s, T, S> {
+        let match_kind = MatchKind::of(self, keys.len(), keys.len());
+        RTLookupResult {
+            val: &self.data,
+            depth: self.depth,
+            keys_used: keys.len(),
+            keep_going: self,
+            params: result_params(params, match_kind),
+            rest: &keys[start..],
+            missed: None,
+            match_kind,
+        }
+    }
+
+    // A node with a fallback answers the keys it couldn't use with the fallback instead of its own data
+    fn stop_here<'s, S: AsRef<str>>(self: &'s Self, keys: &'s [S], start: usize, params: &mut Vec<(&'s str, &'s str, ParamValue)>) -> RTLookupResult<'s, T, S> {
+        let (val, match_kind) = match &self.fallback {
+            Some(fallback) if start < keys.len() => (&**fallback, MatchKind::Fallback),
+            _ => (&*self.data, MatchKind::of(self, start, keys.len())),
+        };
+        RTLookupResult {
+            val,
+            depth: self.depth,
+            keys_used: start,
+            keep_going: self,
+            params: result_params(params, match_kind),
+            rest: &keys[..0],
+            missed: keys.get(start).map(|key| key.as_ref()),
+            match_kind,
+        }
+    }
+
+    // Same as lookup, plus the data of every registered node on the way to the match, the root first
+    // and the matched node last when it is registered. Meant for middleware attached to prefixes
+    pub fn lookup_layers<'s, S: AsRef<str>>(self: &'s Self, keys: &'s [S]) -> Option<(RTLookupResult<'s, T, S>, Vec<&'s T>)> {
+        let found = self.lookup(keys)?;
+        let mut trail = self.trail_to(found.keep_going, keys, 0)?;
+        trail.reverse();
+        let layers = trail.into_iter().filter(|rt| rt.explicit).map(|rt| &*rt.data).collect();
+        Some((found, layers))
+    }
+
+    // The nodes from target back up to self, following the same children lookup_core would try
+    fn trail_to<'s, S: AsRef<str>>(self: &'s Self, target: &RoutingTable<'a, T>, keys: &[S], start: usize) -> Option<Vec<&'s RoutingTable<'a, T>>> {
+        if std::ptr::eq(self, target) { return Some(vec![self]) }
+        let key = keys.get(start)?.as_ref();
+        let candidates = self.map.get(key).into_iter()
+            .chain(self.typed.iter().filter(|(_, constraint, _)| constraint.test(key).is_some()).map(|(_, _, typed_rt)| typed_rt))
+            .chain(self.param.iter().chain(&self.catch_all).map(|(_, child)| &**child));
+        for child in candidates {
+            if let Some(mut trail) = child.trail_to(target, keys, start+1) {
+                trail.push(self);
+                return Some(trail)
+            }
+        }
+        None
+    }
+
+    // Static children that lead to a route, closest to key first and ties in alphabetical order. Only keys
+    // within a third of their length of key (at least 1) count, so "sign-un" finds "sign-in" and "sign-up" but not "users"
+    pub fn suggest<'s>(self: &'s Self, key: &str, limit: usize) -> Vec<&'s str> {
+        let mut close = self.map.iter()
+            .filter(|(_, child)| child.count_routes() > 0)
+            .map(|(sibling, _)| (edit_distance(key, sibling), sibling.as_ref()))
+            .filter(|(distance, sibling)| *distance <= std::cmp::max(1, std::cmp::max(key.chars().count(), sibling.chars().count()) / 3))
+            .collect::<Vec<(usize, &str)>>();
+        close.sort();
+        close.into_iter().take(limit).map(|(_, sibling)| sibling).collect()
+    }
+    
+}
+
+impl<T: Debug + 'static> OwnedRoutingTable<T> {
+
+    pub fn new(root_data: impl Into<Arc<T>>) -> Self {
+        OwnedRoutingTable(RoutingTable::new_core(Entity::Shared(root_data.into()), true, 0))
+    }
+
+    pub fn add_constraint(self: &mut Self, kind: &str, predicate: Predicate) -> () {
+        self.0.add_constraint_core(to_owned_key(kind), predicate)
+    }
+
+    pub fn set_slash_policy(self: &mut Self, policy: SlashPolicy) -> () {
+        self.0.set_slash_policy(policy)
+    }
+
+    pub fn register(self: &mut Self, entity: impl Into<Arc<T>>, route: &[&str]) -> () {
+        self.try_register(entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn reg_more(self: &mut Self, entity: impl Into<Arc<T>>, route: &[OneOrMore]) -> () {
+        self.try_reg_more(entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn reg_parallel(self: &mut Self, entity: impl Into<Arc<T>>, route: &[SerialOrParallel]) -> () {
+        self.try_reg_parallel(entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_register(self: &mut Self, entity: impl Into<Arc<T>>, route: &[&str]) -> Result<(), RegistrationError<'static, T>> {
+        self.0.try_reg_more_core(Entity::Shared(entity.into()), &serial_to_one_or_more(route), to_owned_key)
+    }
+
+    pub fn try_reg_more(self: &mut Self, entity: impl Into<Arc<T>>, route: &[OneOrMore]) -> Result<(), RegistrationError<'static, T>> {
+        self.0.try_reg_more_core(Entity::Shared(entity.into()), route, to_owned_key)
+    }
+
+    pub fn try_reg_parallel(self: &mut Self, entity: impl Into<Arc<T>>, route: &[SerialOrParallel]) -> Result<(), RegistrationError<'static, T>> {
+        self.0.try_reg_more_core(Entity::Shared(entity.into()), &parallel_to_one_or_more(route), to_owned_key)
+    }
+
+    pub fn reg_named(self: &mut Self, name: &str, entity: impl Into<Arc<T>>, route: &[OneOrMore]) -> () {
+        self.try_reg_named(name, entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn reg_method(self: &mut Self, method: &str, entity: impl Into<Arc<T>>, route: &[OneOrMore]) -> () {
+        self.try_reg_method(method, entity, route).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_reg_method(self: &mut Self, method: &str, entity: impl Into<Arc<T>>, route: &[OneOrMore]) -> Result<(), RegistrationError<'static, T>> {
+        self.0.try_reg_core(Entity::Shared(entity.into()), route, Some(method), to_owned_key)
+    }
+
+    pub fn try_reg_named(self: &mut Self, name: &str, entity: impl Into<Arc<T>>, route: &[OneOrMore]) -> Result<(), RegistrationError<'static, T>> {
+        self.0.try_reg_named_core(name, Entity::Shared(entity.into()), route, to_owned_key)
+    }
+
+    pub fn set_fallback(self: &mut Self, prefix: &[&str], entity: impl Into<Arc<T>>) -> () {
+        self.try_set_fallback(prefix, entity).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_set_fallback(self: &mut Self, prefix: &[&str], entity: impl Into<Arc<T>>) -> Result<(), RegistrationError<'static, T>> {
+        self.0.try_set_fallback_core(prefix, Entity::Shared(entity.into()), to_owned_key)
+    }
+
+    pub fn unregister(self: &mut Self, route: &[&str]) -> Option<Entity<'static, T>> {
+        self.0.unregister(route)
+    }
+
+    pub fn replace(self: &mut Self, route: &[&str], entity: impl Into<Arc<T>>) -> Option<Entity<'static, T>> {
+        self.0.replace_core(route, Entity::Shared(entity.into()))
+    }
+
+    pub fn unregister_method(self: &mut Self, method: &str, route: &[&str]) -> Option<Entity<'static, T>> {
+        self.0.unregister_method_core(method, route)
+    }
+
+    pub fn replace_method(self: &mut Self, method: &str, route: &[&str], entity: impl Into<Arc<T>>) -> Option<Entity<'static, T>> {
+        self.0.replace_method_core(method, route, Entity::Shared(entity.into()))
+    }
+
+    pub fn remove_subtree(self: &mut Self, prefix: &[&str]) -> usize {
+        self.0.remove_subtree(prefix)
+    }
+
+    pub fn as_borrowed(self: &Self) -> &RoutingTable<'_, T> {
+        &self.0
+    }
 }
 
-#[derive(Copy, Clone)]
-pub enum OneOrMore<'a> { One(&'a str), More(&'a [&'a str]) }
-pub fn one(str: &str) -> OneOrMore { OneOrMore::One(str) }
-pub fn more<'b>(str_arr: &'b [&str]) -> OneOrMore<'b> { OneOrMore::More(&str_arr) }
+impl<T: Debug + 'static> Deref for OwnedRoutingTable<T> {
+    type Target = RoutingTable<'static, T>;
+    fn deref(self: &Self) -> &Self::Target {
+        &self.0
+    }
+}
 
-#[derive(Copy, Clone)]
-pub enum SerialOrParallel<'a> { Serial(&'a [&'a str]), Parallel(&'a [&'a str]) }
-pub fn ser<'b>(str_arr: &'b [&str]) -> SerialOrParallel<'b> { SerialOrParallel::Serial(str_arr) }
-pub fn par<'b>(str_arr: &'b [&str]) -> SerialOrParallel<'b> { SerialOrParallel::Parallel(str_arr) }
+impl<T: Debug + 'static> From<RoutingTable<'static, T>> for OwnedRoutingTable<T> {
+    fn from(rt: RoutingTable<'static, T>) -> Self {
+        OwnedRoutingTable(rt)
+    }
+}
 
-impl<'a, T: Debug> RoutingTable<'a, T> {
+impl<T: Debug + 'static> From<OwnedRoutingTable<T>> for RoutingTable<'static, T> {
+    fn from(rt_owned: OwnedRoutingTable<T>) -> Self {
+        rt_owned.0
+    }
+}
 
-    pub fn new(root_data:&'a T) -> Self {
-        RoutingTable::new_core(root_data, 0)
+impl<'a, T> Entity<'a, T> {
+    fn to_shared(self: &Self, shared: &mut HashMap<*const T, Arc<T>>) -> Entity<'static, T> where T: Clone + 'static {
+        match self {
+            Entity::Borrowed(entity) => Entity::Shared(shared.entry(*entity as *const T).or_insert_with(|| Arc::new((*entity).clone())).clone()),
+            Entity::Shared(entity) => Entity::Shared(entity.clone()),
+        }
     }
+}
 
-    fn new_core(root_data: &'a T, depth: usize) -> Self {
-        RoutingTable {
-            map: HashMap::new(),
-            data: root_data,
-            depth,
+impl<'a, T> Deref for Entity<'a, T> {
+    type Target = T;
+    fn deref(self: &Self) -> &T {
+        match self {
+            Entity::Borrowed(entity) => entity,
+            Entity::Shared(entity) => entity,
         }
     }
+}
 
-    pub fn register(self: &mut Self, entity: &'a T, route: &'a [&str]) -> () {
-        if route.len() > 0 {
-            let wrapped_route_vec = route[1..].iter().map(|x| OneOrMore::One(x));
-            let route_arr = Box::from_iter(wrapped_route_vec);
-            self.register_one_core(entity, route[0], &route_arr);  // FIXME: Why is this &route_arr not dangling????
-            // box is dropped here
+impl<'a, T> Clone for Entity<'a, T> {
+    fn clone(self: &Self) -> Self {
+        match self {
+            Entity::Borrowed(entity) => Entity::Borrowed(entity),
+            Entity::Shared(entity) => Entity::Shared(Arc::clone(entity)),
         }
-        else {
-            panic!("Double registration error (empty route registration)")
+    }
+}
+
+impl<'a, T: Debug> Debug for Entity<'a, T> {
+    fn fmt(self: &Self, f: &mut Formatter) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<'a> Constraint<'a> {
+
+    pub(super) fn builtin(kind: &str) -> Option<Self> {
+        match kind {
+            "u64" => Some(Constraint::U64),
+            "i64" => Some(Constraint::I64),
+            "uuid" => Some(Constraint::Uuid),
+            "slug" => Some(Constraint::Slug),
+            _ => None,
         }
     }
 
-    pub fn reg_more(self: &mut Self, entity: &'a T, route: &[OneOrMore<'a>]) -> () {
-        if route.len() > 0 {
-            self.register_more_core(entity, route[0], route[1..].into());
+    fn resolve(kind: &str, constraints: &HashMap<Cow<'a, str>, Predicate>) -> Option<Self> {
+        match (Constraint::builtin(kind), constraints.get_key_value(kind)) {
+            (Some(builtin), _) => Some(builtin),
+            (None, Some((kind, predicate))) => Some(Constraint::Custom(kind.clone(), *predicate)),
+            (None, None) => None,
         }
-        else {
-            panic!("Double registration error (empty route registration)")
+    }
+
+    fn kind(self: &Self) -> &str {
+        match self {
+            Constraint::U64 => "u64",
+            Constraint::I64 => "i64",
+            Constraint::Uuid => "uuid",
+            Constraint::Custom(kind, _) => kind,
+            Constraint::Slug => "slug",
         }
     }
 
-    pub fn reg_parallel(self: &mut Self, entity: &'a T, route: &[SerialOrParallel<'a>]) -> () {
-        let mut one_or_more_arr = Vec::<OneOrMore>::new();
-        for item in route {
-            match item {
-                SerialOrParallel::Parallel(str_arr) => {
-                    one_or_more_arr.push(OneOrMore::More(str_arr))
-                }
-                SerialOrParallel::Serial(str_arr) => {
-                    for &serial_piece in *str_arr {
-                        one_or_more_arr.push(OneOrMore::One(serial_piece))
-                    }
-                }
-            }
+    fn rank(self: &Self) -> usize {
+        match self {
+            Constraint::U64 => 0,
+            Constraint::I64 => 1,
+            Constraint::Uuid => 2,
+            Constraint::Custom(_, _) => 3,
+            Constraint::Slug => 4,
         }
-        self.reg_more(entity, &one_or_more_arr)
     }
 
-    fn register_one_core<'b>(self: &mut Self, entity: &'a T, next_rt: &'a str, rest_rt: &'b Box<[OneOrMore<'a>]>) -> () {
+    fn test(self: &Self, key: &str) -> Option<ParamValue> {
+        match self {
+            Constraint::U64 => if is_digits(key) { key.parse().ok().map(ParamValue::U64) } else { None },
+            Constraint::I64 => if is_digits(key.strip_prefix('-').unwrap_or(key)) { key.parse().ok().map(ParamValue::I64) } else { None },
+            Constraint::Uuid => parse_uuid(key).map(ParamValue::Uuid),
+            Constraint::Custom(_, predicate) => if predicate(key) { Some(ParamValue::Str) } else { None },
+            Constraint::Slug => if key.len() > 0 && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_') { Some(ParamValue::Str) } else { None },
+        }
+    }
 
-        if rest_rt.len() == 0 {
-            let find_rt = self.map.get(next_rt);
-            match find_rt {
-                Some(_) => panic!("Double registration error"),
-                None => {
-                    self.map.insert(next_rt, RoutingTable::new_core(entity, self.depth+1));
-                },
-            };
+    fn to_owned_constraint(self: &Self) -> Constraint<'static> {
+        match self {
+            Constraint::U64 => Constraint::U64,
+            Constraint::I64 => Constraint::I64,
+            Constraint::Uuid => Constraint::Uuid,
+            Constraint::Custom(kind, predicate) => Constraint::Custom(to_owned_key(kind), *predicate),
+            Constraint::Slug => Constraint::Slug,
         }
-        else {
-            let find_rt = self.map.get_mut(next_rt);
-            match find_rt {
-                Some(found_rt) => {
-                    found_rt.register_more_core(entity, rest_rt[0], rest_rt[1..].into());
-                }
-                None => {
-                    let mut implicit_layer = RoutingTable::new_core(self.data, self.depth+1);
-                    implicit_layer.register_more_core(entity, rest_rt[0], rest_rt[1..].into());
-                    self.map.insert(next_rt, implicit_layer);
-                },
-            };
+    }
+}
+
+// Everything that decode_url would turn back into something else, plus "/" so a value stays one segment
+const SEGMENT: &AsciiSet = &CONTROLS.add(b' ').add(b'"').add(b'#').add(b'%').add(b'/').add(b'<').add(b'>').add(b'?').add(b'`').add(b'{').add(b'}');
+
+fn encode_segment(key: &str) -> String {
+    utf8_percent_encode(key, SEGMENT).to_string()
+}
+
+// Levenshtein distance over chars, one row at a time
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut row = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, char_a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i+1;
+        for (j, char_b) in b.iter().enumerate() {
+            let above = row[j+1];
+            row[j+1] = if char_a == *char_b { diagonal } else { 1 + diagonal.min(above).min(row[j]) };
+            diagonal = above;
         }
     }
+    row[b.len()]
+}
+
+fn to_owned_key(key: &str) -> Cow<'static, str> {
+    Cow::Owned(key.to_owned())
+}
+
+fn serial_to_one_or_more<'r>(route: &[&'r str]) -> Vec<OneOrMore<'r>> {
+    route.iter().map(|x| OneOrMore::One(x)).collect()
+}
 
-    fn register_more_core(self: &mut Self, entity: &'a T, next_rt: OneOrMore<'a>, rest_rt: Box<[OneOrMore<'a>]>) -> () {
-        match next_rt {
-            OneOrMore::One(one_rt) => { 
-                self.register_one_core(entity, one_rt, &rest_rt)
+fn parallel_to_one_or_more<'r>(route: &[SerialOrParallel<'r>]) -> Vec<OneOrMore<'r>> {
+    let mut one_or_more_arr = Vec::<OneOrMore>::new();
+    for item in route {
+        match item {
+            SerialOrParallel::Parallel(str_arr) => {
+                one_or_more_arr.push(OneOrMore::More(str_arr))
             }
-            OneOrMore::More(more_rt) => { 
-                for each_rt in more_rt { self.register_one_core(entity, each_rt, &rest_rt) }    
+            SerialOrParallel::Serial(str_arr) => {
+                for &serial_piece in *str_arr {
+                    one_or_more_arr.push(OneOrMore::One(serial_piece))
+                }
             }
         }
     }
-    
-    pub fn lookup(self: &'a Self, keys: &'a [&str]) -> Option<RTLookupResult<'a, T>> {
-        self.lookup_core(keys, 0)
+    one_or_more_arr
+}
+
+// An Exact match ends the lookup, so it can take the params buffer instead of copying it
+fn result_params<'s>(params: &mut Vec<(&'s str, &'s str, ParamValue)>, match_kind: MatchKind) -> Vec<(&'s str, &'s str, ParamValue)> {
+    if match_kind == MatchKind::Exact { std::mem::take(params) } else { params.clone() }
+}
+
+// Position of the first OneOrMore::More without alternatives, a route with one expands to nothing
+fn without_alternatives(route: &[OneOrMore]) -> Option<usize> {
+    route.iter().position(|next_rt| matches!(next_rt, OneOrMore::More(more_rt) if more_rt.len() == 0))
+}
+
+// Cartesian product of every OneOrMore::More in the route, each expanded route comes with
+// the (position, alternative) picked for every More it went through
+fn expand<'a>(route: &[OneOrMore<'a>]) -> Vec<(Vec<&'a str>, Vec<(usize, &'a str)>)> {
+    let mut expanded = vec![(Vec::new(), Vec::new())];
+    for (position, next_rt) in route.iter().enumerate() {
+        let alternatives = match next_rt {
+            OneOrMore::One(one_rt) => vec![(*one_rt, None)],
+            OneOrMore::More(more_rt) => more_rt.iter().map(|each_rt| (*each_rt, Some((position, *each_rt)))).collect(),
+        };
+        expanded = expanded.iter().flat_map(|(route_so_far, expansion_so_far)| alternatives.iter().map(move |(each_rt, picked)| {
+            let mut full_route = route_so_far.clone();
+            let mut expansion = expansion_so_far.clone();
+            full_route.push(*each_rt);
+            expansion.extend(picked);
+            (full_route, expansion)
+        })).collect();
     }
-    
-    fn lookup_core(self: &'a Self, keys: &'a [&str], start: usize) -> Option<RTLookupResult<'a, T>> {
-        let key_start = keys.get(start);
-        //println!("{:?}[{}] = {:?}", keys, start, key_start );
-        if let Some(key) = key_start {
-            let next_map = self.map.get(key);
-            if let Some(map) = next_map {
-                return map.lookup_core(keys, start+1);
-            }
+    expanded
+}
+
+// Whether two expansions of the same registration end at the same node
+fn same_route<'a, T: Debug>(route_a: &[&str], route_b: &[&str]) -> Result<bool, RegistrationErrorKind<'a, T>> {
+    for (key_a, key_b) in route_a.iter().zip(route_b) {
+        let (name_a, name_b) = match (parse_segment(key_a), parse_segment(key_b)) {
+            (Segment::Literal(literal_a), Segment::Literal(literal_b)) if literal_a == literal_b => continue,
+            (Segment::Typed(name_a, kind_a), Segment::Typed(name_b, kind_b)) if kind_a == kind_b => (name_a, name_b),
+            (Segment::Param(name_a), Segment::Param(name_b)) => (name_a, name_b),
+            (Segment::CatchAll(name_a), Segment::CatchAll(name_b)) => (name_a, name_b),
+            _ => return Ok(false),
+        };
+        if name_a != name_b { return Err(RegistrationErrorKind::ConflictingNames(name_a.to_string(), name_b.to_string())) }
+    }
+    Ok(route_a.len() == route_b.len())
+}
+
+fn is_digits(key: &str) -> bool {
+    key.len() > 0 && key.bytes().all(|b| b.is_ascii_digit())
+}
+
+// 8-4-4-4-12 hex digits, e.g. 123e4567-e89b-12d3-a456-426614174000
+fn parse_uuid(key: &str) -> Option<u128> {
+    let groups = key.split('-').collect::<Vec<&str>>();
+    let lengths = groups.iter().map(|group| group.len()).collect::<Vec<usize>>();
+    if lengths != [8, 4, 4, 4, 12] || !groups.iter().all(|group| group.bytes().all(|b| b.is_ascii_hexdigit())) {
+        return None
+    }
+    u128::from_str_radix(&groups.concat(), 16).ok()
+}
+
+// ":name" matches any single key and captures it under "name",
+// "{name:kind}" does the same only if the key satisfies the constraint "kind" ("{kind}" is short for "{kind:kind}"),
+// "*name" can only be the last segment and captures all remaining keys (at least one)
+enum Segment<'a> { Literal(&'a str), Typed(&'a str, &'a str), Param(&'a str), CatchAll(&'a str) }
+
+fn parse_segment(key: &str) -> Segment {
+    if key.len() > 2 && key.starts_with('{') && key.ends_with('}') {
+        let inner = &key[1..key.len()-1];
+        match inner.find(':') {
+            Some(pos) => Segment::Typed(&inner[..pos], &inner[pos+1..]),
+            None => Segment::Typed(inner, inner),
         }
-        Some(RTLookupResult {
-            val: self.data,
-            depth: self.depth,
-            keys_used: start,
-            keep_going: self,
-        })
     }
-    
+    else if key.len() > 1 && key.starts_with(':') { Segment::Param(&key[1..]) }
+    else if key.len() > 1 && key.starts_with('*') { Segment::CatchAll(&key[1..]) }
+    else { Segment::Literal(key) }
 }
 
 pub trait Boring {
@@ -152,12 +1238,26 @@ impl<T: Debug> Boring for RoutingTable<'_, T> {
 
 mod test {
 
-    use super::{RoutingTable, one, more, par, ser};
+    use std::borrow::Cow;
+    use std::sync::Arc;
+    use super::super::parse_url::{ParsedUrl, SlashPolicy};
+    use super::{edit_distance, RoutingTable, OwnedRoutingTable, RegistrationError, RegistrationErrorKind, UrlForError, MatchKind, MethodMatch, ParamValue, one, more, par, ser};
 
     const BOTTOM_FALLBACK: &i32 = &14; 
     const APP_API_V4_SIGNUP: &i32 = &15;
     const APP_API_V4_SIGNIN: &i32 = &16; 
     const APP_API_V4_SIGNOUT: &i32 = &17; 
+    const APP_USERS_ME: &i32 = &18;
+    const APP_USERS_ID: &i32 = &19;
+    const APP_USERS_ID_POSTS: &i32 = &20;
+    const APP_USERS_ID_POSTS_ID: &i32 = &21;
+    const APP_STATIC: &i32 = &22;
+    const APP_STATIC_INDEX: &i32 = &23;
+    const APP_ORDERS_ID: &i32 = &24;
+    const APP_ORDERS_UUID: &i32 = &25;
+    const APP_ORDERS_HEX: &i32 = &26;
+    const APP_ORDERS_SLUG: &i32 = &27;
+    const APP_ORDERS_NEW: &i32 = &28;
 
     #[test]
     fn simple_case() {
@@ -166,7 +1266,7 @@ mod test {
         rt.register(APP_API_V4_SIGNIN , &["api", "v4", "sign-in" ]);
         rt.register(APP_API_V4_SIGNOUT, &["api", "v4", "sign-out"]);
         
-        assert_eq!(rt.lookup(&[                              ]).unwrap().val, BOTTOM_FALLBACK);
+        assert_eq!(rt.lookup::<&str>(&[                              ]).unwrap().val, BOTTOM_FALLBACK);
         assert_eq!(rt.lookup(&["api"                         ]).unwrap().val, BOTTOM_FALLBACK);
         assert_eq!(rt.lookup(&["api", "v4"                   ]).unwrap().val, BOTTOM_FALLBACK);
         assert_eq!(rt.lookup(&["api", "v4", "sign-up"        ]).unwrap().val, APP_API_V4_SIGNUP);
@@ -185,7 +1285,7 @@ mod test {
         rt_more.reg_more(APP_API_V4_SIGNIN, &[gpp, lr, one("api"), one("v4"), one("sign-in")]);
         rt_more.reg_more(APP_API_V4_SIGNOUT, &[gpp, lr, one("api"), one("v4"), one("sign-out")]);
 
-        // println!("{:#?}", rt_more);
+        // println!("{}", rt_more.render_tree());
 
         assert_eq!(rt_more.lookup(&["GET" , "localhost" ,                               ]).unwrap().val, BOTTOM_FALLBACK);
         assert_eq!(rt_more.lookup(&["GET" , "localhost" , "api"                         ]).unwrap().val, BOTTOM_FALLBACK);
@@ -209,7 +1309,7 @@ mod test {
         rt_more.reg_parallel(APP_API_V4_SIGNIN,  &[ gpp , lr , ser(&["api", "v4", "sign-in" ]) ] );
         rt_more.reg_parallel(APP_API_V4_SIGNOUT, &[ gpp , lr , ser(&["api", "v4", "sign-out"]) ] );
 
-        // println!("{:#?}", rt_more);
+        // println!("{}", rt_more.render_tree());
 
         assert_eq!(rt_more.lookup(&["GET" , "localhost" ,                               ]).unwrap().val, BOTTOM_FALLBACK);
         assert_eq!(rt_more.lookup(&["GET" , "localhost" , "api"                         ]).unwrap().val, BOTTOM_FALLBACK);
@@ -240,5 +1340,565 @@ mod test {
         let mut rt_panic = RoutingTable::new(BOTTOM_FALLBACK);
         rt_panic.reg_more(APP_API_V4_SIGNUP, &[]);
     }
-}
 
+    #[test]
+    fn named_params() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_USERS_ME         , &["users", "me"                      ]);
+        rt.register(APP_USERS_ID         , &["users", ":id"                     ]);
+        rt.register(APP_USERS_ID_POSTS   , &["users", ":id", "posts"            ]);
+        rt.register(APP_USERS_ID_POSTS_ID, &["users", ":id", "posts", ":post_id"]);
+
+        let found = rt.lookup(&["users", "42", "posts"]).unwrap();
+        assert_eq!(found.val, APP_USERS_ID_POSTS);
+        assert_eq!(found.param("id"), Some("42"));
+        assert_eq!(found.param("post_id"), None);
+
+        let found = rt.lookup(&["users", "42", "posts", "7"]).unwrap();
+        assert_eq!(found.val, APP_USERS_ID_POSTS_ID);
+        assert_eq!(found.params, vec![("id", "42", ParamValue::Str), ("post_id", "7", ParamValue::Str)]);
+
+        let found = rt.lookup(&["users", "me"]).unwrap();
+        assert_eq!(found.val, APP_USERS_ME);
+        assert_eq!(found.params, vec![]);
+
+        assert_eq!(rt.lookup(&["users", "42"                ]).unwrap().val, APP_USERS_ID);
+        assert_eq!(rt.lookup(&["users", "42", "DNE"         ]).unwrap().val, APP_USERS_ID);
+        assert_eq!(rt.lookup(&["users"                      ]).unwrap().val, BOTTOM_FALLBACK);
+        assert_eq!(rt.lookup(&["DNE", "42"                  ]).unwrap().val, BOTTOM_FALLBACK);
+    }
+
+    #[test]
+    #[should_panic]
+    fn conflicting_param_names_panic() {
+        let mut rt_panic = RoutingTable::new(BOTTOM_FALLBACK);
+        rt_panic.register(APP_USERS_ID, &["users", ":id"]);
+        rt_panic.register(APP_USERS_ID_POSTS, &["users", ":user_id", "posts"]);
+    }
+
+    #[test]
+    fn catch_all_tail() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_STATIC      , &["static", "*path"     ]);
+        rt.register(APP_STATIC_INDEX, &["static", "index.html"]);
+        rt.register(APP_USERS_ID    , &["users", ":id", "*rest"]);
+
+        let found = rt.lookup(&["static", "css", "site.css"]).unwrap();
+        assert_eq!(found.val, APP_STATIC);
+        assert_eq!(found.rest(), &["css", "site.css"]);
+        assert_eq!(found.keys_used, 3);
+        assert_eq!(found.depth, 2);
+
+        let found = rt.lookup(&["static", "index.html"]).unwrap();
+        assert_eq!(found.val, APP_STATIC_INDEX);
+        assert_eq!(found.rest().len(), 0);
+
+        let found = rt.lookup(&["static"]).unwrap();
+        assert_eq!(found.val, BOTTOM_FALLBACK);
+        assert_eq!(found.rest().len(), 0);
+
+        let found = rt.lookup(&["users", "42", "a", "b"]).unwrap();
+        assert_eq!(found.val, APP_USERS_ID);
+        assert_eq!(found.param("id"), Some("42"));
+        assert_eq!(found.rest(), &["a", "b"]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn catch_all_not_last_panic() {
+        let mut rt_panic = RoutingTable::new(BOTTOM_FALLBACK);
+        rt_panic.register(APP_STATIC, &["static", "*path", "index.html"]);
+    }
+
+    #[test]
+    fn typed_constraints() {
+        fn is_hex(key: &str) -> bool { key.starts_with("0x") && key.len() > 2 && key[2..].bytes().all(|b| b.is_ascii_hexdigit()) }
+
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.add_constraint("hex", is_hex);
+        rt.register(APP_ORDERS_SLUG, &["orders", "{name:slug}"]);
+        rt.register(APP_ORDERS_HEX , &["orders", "{id:hex}"   ]);
+        rt.register(APP_ORDERS_UUID, &["orders", "{uuid}"     ]);
+        rt.register(APP_ORDERS_ID  , &["orders", "{id:u64}"   ]);
+        rt.register(APP_ORDERS_NEW , &["orders", "new"        ]);
+
+        let found = rt.lookup(&["orders", "42"]).unwrap();
+        assert_eq!(found.val, APP_ORDERS_ID);
+        assert_eq!(found.param("id"), Some("42"));
+        assert_eq!(found.typed("id"), Some(ParamValue::U64(42)));
+
+        let found = rt.lookup(&["orders", "123e4567-e89b-12d3-a456-426614174000"]).unwrap();
+        assert_eq!(found.val, APP_ORDERS_UUID);
+        assert_eq!(found.typed("uuid"), Some(ParamValue::Uuid(0x123e4567_e89b_12d3_a456_426614174000)));
+
+        let found = rt.lookup(&["orders", "0xff"]).unwrap();
+        assert_eq!(found.val, APP_ORDERS_HEX);
+        assert_eq!(found.typed("id"), Some(ParamValue::Str));
+
+        let found = rt.lookup(&["orders", "abc"]).unwrap();
+        assert_eq!(found.val, APP_ORDERS_SLUG);
+        assert_eq!(found.param("name"), Some("abc"));
+        assert_eq!(found.typed("id"), None);
+
+        assert_eq!(rt.lookup(&["orders", "new"      ]).unwrap().val, APP_ORDERS_NEW);
+        assert_eq!(rt.lookup(&["orders", "a b"      ]).unwrap().val, BOTTOM_FALLBACK);
+        assert_eq!(rt.lookup(&["orders", "99999999999999999999"]).unwrap().val, APP_ORDERS_SLUG);
+
+        let mut rt_signed = RoutingTable::new(BOTTOM_FALLBACK);
+        rt_signed.register(APP_ORDERS_ID, &["offset", "{by:i64}"]);
+        assert_eq!(rt_signed.lookup(&["offset", "-3"]).unwrap().typed("by"), Some(ParamValue::I64(-3)));
+        assert_eq!(rt_signed.lookup(&["offset", "-" ]).unwrap().val, BOTTOM_FALLBACK);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_constraint_panic() {
+        let mut rt_panic = RoutingTable::new(BOTTOM_FALLBACK);
+        rt_panic.register(APP_ORDERS_ID, &["orders", "{id:hex}"]);
+    }
+
+    #[test]
+    fn fallible_registration() {
+        let gp = more(&["GET", "POST"]);
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.reg_more(APP_API_V4_SIGNUP, &[one("POST"), one("api"), one("v4"), one("sign-up")]);
+
+        let error = rt.try_reg_more(APP_API_V4_SIGNIN, &[gp, one("api"), one("v4"), one("sign-up")]).unwrap_err();
+        assert_eq!(error.route, vec!["POST", "api", "v4", "sign-up"]);
+        assert_eq!(error.expansion, vec![(0, String::from("POST"))]);
+        assert!(matches!(&error.kind, RegistrationErrorKind::Duplicate(existing) if **existing == *APP_API_V4_SIGNUP));
+        assert_eq!(error.to_string(), "Double registration error: /POST/api/v4/sign-up is already registered to 15 (expanded from #0 = POST)");
+        assert_eq!(rt.lookup(&["GET", "api", "v4", "sign-up"]).unwrap().val, BOTTOM_FALLBACK);
+
+        let error = rt.try_reg_parallel(APP_API_V4_SIGNIN, &[par(&["PUT", "PUT"]), ser(&["api", "v4", "sign-in"])]).unwrap_err();
+        assert_eq!(error.expansion, vec![(0, String::from("PUT"))]);
+        assert!(matches!(&error.kind, RegistrationErrorKind::Duplicate(existing) if **existing == *APP_API_V4_SIGNIN));
+        assert_eq!(rt.lookup(&["PUT", "api", "v4", "sign-in"]).unwrap().val, BOTTOM_FALLBACK);
+
+        rt.try_register(APP_USERS_ID, &["users", ":id"]).unwrap();
+        let error = rt.try_register(APP_USERS_ID_POSTS, &["users", ":user", "posts"]).unwrap_err();
+        assert!(matches!(&error.kind, RegistrationErrorKind::ConflictingNames(existing, name) if existing == "id" && name == "user"));
+
+        assert!(matches!(rt.try_register(APP_STATIC, &["static", "*path", "x"]).unwrap_err().kind, RegistrationErrorKind::CatchAllNotLast));
+        assert!(matches!(rt.try_register(APP_ORDERS_ID, &["orders", "{id:hex}"]).unwrap_err().kind, RegistrationErrorKind::UnknownConstraint(kind) if kind == "hex"));
+        assert!(matches!(rt.try_reg_more(APP_ORDERS_ID, &[]).unwrap_err().kind, RegistrationErrorKind::EmptyRoute));
+        assert!(matches!(rt.try_reg_more(APP_ORDERS_ID, &[one("orders"), more(&[])]).unwrap_err().kind, RegistrationErrorKind::NoAlternatives(1)));
+        rt.set_slash_policy(SlashPolicy::Ignore);
+        assert!(matches!(rt.try_register(APP_ORDERS_ID, &["api", ""]).unwrap_err().kind, RegistrationErrorKind::EmptySegment));
+        assert!(matches!(rt.try_reg_more(APP_ORDERS_ID, &[more(&["orders", ""]), one(":id")]).unwrap_err().kind, RegistrationErrorKind::EmptySegment));
+        assert!(matches!(rt.try_set_fallback(&["", "api"], APP_ORDERS_ID).unwrap_err().kind, RegistrationErrorKind::EmptySegment));
+        assert!(matches!(rt.try_reg_method("GET", APP_ORDERS_ID, &[more(&[]), one("orders")]).unwrap_err().kind, RegistrationErrorKind::NoAlternatives(0)));
+    }
+
+    #[test]
+    fn owned_routing_table() {
+        let mut rt_owned = OwnedRoutingTable::new(14);
+        for (entity, route) in vec![(15, "api/v4/sign-up"), (16, "api/v4/sign-in"), (19, "users/{id:u64}")] {
+            let route = String::from(route);
+            let keys = route.split('/').collect::<Vec<&str>>();
+            rt_owned.register(entity, &keys);
+        }
+        rt_owned.reg_parallel(Arc::new(17), &[par(&["api", "API"]), ser(&["v4", "sign-out"])]);
+
+        assert_eq!(*rt_owned.lookup(&["api", "v4", "sign-up" ]).unwrap().val, 15);
+        assert_eq!(*rt_owned.lookup(&["API", "v4", "sign-out"]).unwrap().val, 17);
+        assert_eq!(*rt_owned.lookup(&["api", "v4", "DNE"     ]).unwrap().val, 14);
+        assert_eq!(rt_owned.lookup(&["users", "42"]).unwrap().typed("id"), Some(ParamValue::U64(42)));
+        assert!(rt_owned.try_register(15, &["api", "v4", "sign-up"]).is_err());
+
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNUP, &["api", "v4", "sign-up"]);
+        rt.register(APP_USERS_ID     , &["users", ":id"       ]);
+        let rt_converted = rt.to_owned_table();
+        drop(rt);
+
+        assert_eq!(rt_converted.lookup(&["api", "v4", "sign-up"]).unwrap().val, APP_API_V4_SIGNUP);
+        assert_eq!(rt_converted.lookup(&["users", "42"]).unwrap().param("id"), Some("42"));
+        let root_val = rt_converted.lookup::<&str>(&[]).unwrap().val;
+        assert!(std::ptr::eq(root_val, rt_converted.lookup(&["api", "v4"]).unwrap().val));
+        assert_eq!(rt_converted.as_borrowed().lookup(&["api", "v4", "sign-up"]).unwrap().val, APP_API_V4_SIGNUP);
+
+        let rt_back: RoutingTable<'static, i32> = rt_owned.into();
+        assert_eq!(*rt_back.lookup(&["api", "v4", "sign-in"]).unwrap().val, 16);
+    }
+
+    #[test]
+    fn unregister_replace_and_prune() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_USERS_ID_POSTS, &["users", ":id", "posts"]);
+        assert_eq!(*rt.unregister(&["users", ":id", "posts"]).unwrap(), *APP_USERS_ID_POSTS);
+        assert_eq!(rt.lookup(&["users", "42", "posts"]).unwrap().val, BOTTOM_FALLBACK);
+        assert!(rt.map.is_empty());
+        assert!(rt.unregister(&["users", ":id", "posts"]).is_none());
+
+        // implicit layers under a removed route fall back to what they would have inherited without it
+        rt.register(APP_USERS_ID         , &["users", ":id"                     ]);
+        rt.register(APP_USERS_ID_POSTS_ID, &["users", ":id", "posts", ":post_id"]);
+        assert_eq!(rt.lookup(&["users", "42", "posts"]).unwrap().val, APP_USERS_ID);
+        assert!(rt.unregister(&["users", ":user"]).is_none());
+        assert!(rt.unregister(&["users", ":id", "posts"]).is_none());
+        assert_eq!(*rt.unregister(&["users", ":id"]).unwrap(), *APP_USERS_ID);
+        assert_eq!(rt.lookup(&["users", "42"              ]).unwrap().val, BOTTOM_FALLBACK);
+        assert_eq!(rt.lookup(&["users", "42", "posts"     ]).unwrap().val, BOTTOM_FALLBACK);
+        assert_eq!(rt.lookup(&["users", "42", "posts", "7"]).unwrap().val, APP_USERS_ID_POSTS_ID);
+
+        // registering onto an implicit layer promotes it, whichever order the routes came in
+        rt.register(APP_USERS_ID, &["users", ":id"]);
+        assert_eq!(rt.lookup(&["users", "42", "posts"]).unwrap().val, APP_USERS_ID);
+        assert_eq!(*rt.replace(&["users", ":id"], APP_USERS_ME).unwrap(), *APP_USERS_ID);
+        assert_eq!(rt.lookup(&["users", "42"         ]).unwrap().val, APP_USERS_ME);
+        assert_eq!(rt.lookup(&["users", "42", "posts"]).unwrap().val, APP_USERS_ME);
+        assert!(rt.replace(&["users", ":id", "posts"], APP_USERS_ME).is_none());
+
+        rt.register(APP_API_V4_SIGNUP, &["api", "v4", "sign-up"]);
+        rt.register(APP_API_V4_SIGNIN, &["api", "v4", "sign-in"]);
+        assert_eq!(rt.remove_subtree(&["api", "v4"]), 2);
+        assert!(rt.map.get("api").is_none());
+        assert_eq!(rt.remove_subtree(&["users"]), 2);
+        assert!(rt.map.is_empty());
+    }
+
+    #[test]
+    fn url_for_named_routes() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.reg_named("auth.sign_in", APP_API_V4_SIGNIN, &[more(&["GET", "POST"]), one("api"), one("v4"), one("sign-in")]);
+        rt.reg_named("users.posts", APP_USERS_ID_POSTS, &[one("users"), one("{id:u64}"), more(&["posts", "articles"]), one("*path")]);
+        rt.reg_named("users.named", APP_USERS_ID, &[one("users"), one(":name")]);
+
+        assert_eq!(rt.url_for("auth.sign_in", &[]).unwrap(), "/GET/api/v4/sign-in");
+        assert_eq!(rt.url_for_alt("auth.sign_in", &[], &[(0, "POST")]).unwrap(), "/POST/api/v4/sign-in");
+        assert_eq!(rt.url_for_alt("users.posts", &[("id", "42"), ("path", "a b/ü?")], &[(2, "articles")]).unwrap(), "/users/42/articles/a%20b/%C3%BC%3F");
+
+        // What url_for builds has to come back out of decode_url and lookup unchanged
+        let url = rt.url_for("users.named", &[("name", "50%/off #1")]).unwrap();
+        assert_eq!(url, "/users/50%25%2Foff%20%231");
+        let decoded = url[1..].split('/').map(|segment| percent_encoding::percent_decode_str(segment).decode_utf8().unwrap().to_string()).collect::<Vec<String>>();
+        let keys = decoded.iter().map(|segment| segment.as_str()).collect::<Vec<&str>>();
+        let found = rt.lookup(&keys).unwrap();
+        assert_eq!(found.val, APP_USERS_ID);
+        assert_eq!(found.param("name"), Some("50%/off #1"));
+
+        assert_eq!(rt.url_for("DNE", &[]), Err(UrlForError::UnknownName(String::from("DNE"))));
+        assert_eq!(rt.url_for("users.named", &[]), Err(UrlForError::MissingParam(String::from("name"))));
+        assert_eq!(rt.url_for("users.posts", &[("id", "x"), ("path", "a")]), Err(UrlForError::ConstraintFailed(String::from("id"), String::from("x"))));
+        assert_eq!(rt.url_for_alt("auth.sign_in", &[], &[(0, "PUT")]), Err(UrlForError::NotAnAlternative(0, String::from("PUT"))));
+
+        let error = rt.try_reg_named("auth.sign_in", APP_API_V4_SIGNOUT, &[one("sign-out")]).unwrap_err();
+        assert!(matches!(&error.kind, RegistrationErrorKind::DuplicateName(name) if name == "auth.sign_in"));
+        assert_eq!(rt.lookup(&["sign-out"]).unwrap().val, BOTTOM_FALLBACK);
+
+        assert!(matches!(rt.try_reg_named("auth.sign_in", APP_API_V4_SIGNOUT, &[one("api"), more(&[])]).unwrap_err().kind, RegistrationErrorKind::NoAlternatives(1)));
+        assert!(matches!(rt.try_reg_named("users.none", APP_API_V4_SIGNOUT, &[more(&[])]).unwrap_err().kind, RegistrationErrorKind::NoAlternatives(0)));
+
+        let mut rt_owned = rt.to_owned_table();
+        assert_eq!(rt_owned.url_for("users.named", &[("name", "bob")]).unwrap(), "/users/bob");
+
+        // Names go away with the routes they were registered for, removing one alternative is enough
+        rt.unregister(&["POST", "api", "v4", "sign-in"]);
+        assert_eq!(rt.url_for("auth.sign_in", &[]), Err(UrlForError::UnknownName(String::from("auth.sign_in"))));
+        rt.remove_subtree(&["users", "{id:u64}"]);
+        assert_eq!(rt.url_for("users.posts", &[("id", "42"), ("path", "a")]), Err(UrlForError::UnknownName(String::from("users.posts"))));
+        assert_eq!(rt.url_for("users.named", &[("name", "bob")]).unwrap(), "/users/bob");
+        rt_owned.remove_subtree(&[]);
+        assert_eq!(rt_owned.url_for("users.named", &[("name", "bob")]), Err(UrlForError::UnknownName(String::from("users.named"))));
+    }
+
+    #[test]
+    fn match_kinds() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNIN, &["api", "v4", "sign-in"]);
+        rt.register(APP_USERS_ID     , &["users", ":id"        ]);
+        rt.register(APP_STATIC       , &["static", "*path"     ]);
+
+        let kind = |keys: &[&str]| rt.lookup(keys).unwrap().match_kind();
+        assert_eq!(kind(&[                              ]), MatchKind::Fallback);
+        assert_eq!(kind(&["api", "v4", "sign-in"        ]), MatchKind::Exact);
+        assert_eq!(kind(&["users", "42"                 ]), MatchKind::Exact);
+        assert_eq!(kind(&["static", "css", "site.css"   ]), MatchKind::Exact);
+        assert_eq!(kind(&["api", "v4", "sign-in", "tail"]), MatchKind::Prefix);
+        assert_eq!(kind(&["users", "42", "DNE"          ]), MatchKind::Prefix);
+        assert_eq!(kind(&["api", "v4"                   ]), MatchKind::Fallback);
+        assert_eq!(kind(&["api", "v4", "DNE"            ]), MatchKind::Fallback);
+        assert_eq!(kind(&["static"                      ]), MatchKind::Fallback);
+        assert_eq!(kind(&["DNE"                         ]), MatchKind::Fallback);
+
+        // The root serves "/" only through methods registered for the empty route
+        assert_eq!(rt.lookup_method::<&str>("GET", &[]).unwrap().1, MethodMatch::NotFound);
+        rt.reg_method("GET", APP_USERS_ME, &[]);
+        assert_eq!(rt.lookup::<&str>(&[]).unwrap().match_kind(), MatchKind::Exact);
+        assert_eq!(rt.lookup_method::<&str>("GET", &[]).unwrap().1, MethodMatch::Handler(APP_USERS_ME));
+        assert_eq!(rt.lookup_method("GET", &["DNE"]).unwrap().1, MethodMatch::NotFound);
+        assert_eq!(rt.routes().next().map(|entry| (entry.route, entry.method)), Some((vec![], Some(String::from("GET")))));
+    }
+
+    #[test]
+    fn method_routing() {
+        fn method<'s>(rt: &'s RoutingTable<i32>, method: &str, keys: &'s [&'s str]) -> MethodMatch<'s, i32> { rt.lookup_method(method, keys).unwrap().1 }
+
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.reg_method("POST"  , APP_API_V4_SIGNUP , &[one("api"), one("v4"), one("sign-up")]);
+        rt.reg_method("GET"   , APP_USERS_ID      , &[one("users"), one(":id")]);
+        rt.reg_method("DELETE", APP_USERS_ID_POSTS, &[one("users"), one(":id")]);
+        rt.register(APP_STATIC, &["static", "*path"]);
+
+        assert_eq!(method(&rt, "POST"   , &["api", "v4", "sign-up"]), MethodMatch::Handler(APP_API_V4_SIGNUP));
+        assert_eq!(method(&rt, "GET"    , &["api", "v4", "sign-up"]), MethodMatch::NotAllowed(vec!["POST", "OPTIONS"]));
+        assert_eq!(method(&rt, "GET"    , &["users", "42"         ]), MethodMatch::Handler(APP_USERS_ID));
+        assert_eq!(method(&rt, "HEAD"   , &["users", "42"         ]), MethodMatch::Handler(APP_USERS_ID));
+        assert_eq!(method(&rt, "DELETE" , &["users", "42"         ]), MethodMatch::Handler(APP_USERS_ID_POSTS));
+        assert_eq!(method(&rt, "OPTIONS", &["users", "42"         ]), MethodMatch::Options(vec!["GET", "DELETE", "HEAD", "OPTIONS"]));
+        assert_eq!(method(&rt, "PUT"    , &["users", "42"         ]), MethodMatch::NotAllowed(vec!["GET", "DELETE", "HEAD", "OPTIONS"]));
+        assert_eq!(method(&rt, "PUT"    , &["static", "a.css"     ]), MethodMatch::Handler(APP_STATIC));
+        assert_eq!(method(&rt, "POST"   , &["api", "v4"           ]), MethodMatch::NotFound);
+        assert_eq!(method(&rt, "POST"   , &["api", "v4", "sign-up", "tail"]), MethodMatch::NotFound);
+        assert_eq!(method(&rt, "GET"    , &["DNE"                 ]), MethodMatch::NotFound);
+
+        // Method-only nodes have no data of their own, but they are still registered
+        assert_eq!(rt.lookup(&["users", "42"]).unwrap().match_kind(), MatchKind::Exact);
+        assert_eq!(rt.lookup(&["users", "42", "DNE"]).unwrap().match_kind(), MatchKind::Prefix);
+        let routes = rt.routes().map(|entry| (entry.route.join("/"), entry.method)).collect::<Vec<(String, Option<String>)>>();
+        assert_eq!(routes[..3], [(String::from("api/v4/sign-up"), Some(String::from("POST"))), (String::from("static/*path"), None), (String::from("users/:id"), Some(String::from("GET")))]);
+
+        let error = rt.try_reg_method("GET", APP_USERS_ME, &[one("users"), one(":id")]).unwrap_err();
+        assert!(matches!(&error.kind, RegistrationErrorKind::Duplicate(existing) if **existing == *APP_USERS_ID));
+        rt.reg_method("GET", APP_STATIC_INDEX, &[one("static"), one("*path")]);
+        assert_eq!(method(&rt, "GET", &["static", "a.css"]), MethodMatch::Handler(APP_STATIC_INDEX));
+
+        // unregister leaves method registrations alone, unregister_method only removes the one method
+        assert!(rt.unregister(&["users", ":id"]).is_none());
+        assert_eq!(*rt.replace_method("DELETE", &["users", ":id"], APP_USERS_ME).unwrap(), *APP_USERS_ID_POSTS);
+        assert!(rt.replace_method("PUT", &["users", ":id"], APP_USERS_ME).is_none());
+        assert_eq!(*rt.unregister_method("GET", &["users", ":id"]).unwrap(), *APP_USERS_ID);
+        assert_eq!(method(&rt, "GET", &["users", "42"]), MethodMatch::NotAllowed(vec!["DELETE", "OPTIONS"]));
+        assert_eq!(*rt.unregister_method("DELETE", &["users", ":id"]).unwrap(), *APP_USERS_ME);
+        assert!(rt.map.get("users").is_none());
+        assert!(rt.unregister_method("POST", &["api", "v4", "sign-up", "DNE"]).is_none());
+
+        rt.reg_method("GET", APP_USERS_ME, &[]);
+        assert_eq!(rt.remove_subtree(&[]), 4);
+        assert_eq!(rt.routes().count(), 0);
+    }
+
+    // Every case pins (entity, params, keys_used, match kind) for a route set where several
+    // branches accept the same keys, so any change to priority or backtracking shows up here
+    #[test]
+    fn backtracking_conformance() {
+        const FILES_NEW: &i32 = &40;
+        const FILES_NEW_DRAFT: &i32 = &41;
+        const FILES_ID: &i32 = &42;
+        const FILES_NAME: &i32 = &43;
+        const FILES_NAME_EDIT: &i32 = &44;
+        const FILES_PATH: &i32 = &45;
+        const DOCS_API_REFERENCE: &i32 = &46;
+        const DOCS_SECTION_INTRO: &i32 = &47;
+        const A_X_C: &i32 = &48;
+        const A_B_Y: &i32 = &49;
+
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(FILES_NEW         , &["files", "new"             ]);
+        rt.register(FILES_NEW_DRAFT   , &["files", "new", "draft"    ]);
+        rt.register(FILES_ID          , &["files", "{id:u64}"        ]);
+        rt.register(FILES_NAME        , &["files", ":name"           ]);
+        rt.register(FILES_NAME_EDIT   , &["files", ":name", "edit"   ]);
+        rt.register(FILES_PATH        , &["files", "*path"           ]);
+        rt.register(DOCS_API_REFERENCE, &["docs", "api", "reference" ]);
+        rt.register(DOCS_SECTION_INTRO, &["docs", ":section", "intro"]);
+        rt.register(A_X_C             , &["a", ":x", "c"             ]);
+        rt.register(A_B_Y             , &["a", "b", ":y"             ]);
+
+        let cases: &[(&[&str], &i32, &[(&str, &str)], usize, MatchKind)] = &[
+            (&[                           ], BOTTOM_FALLBACK   , &[                  ], 0, MatchKind::Fallback),
+            (&["files"                    ], BOTTOM_FALLBACK   , &[                  ], 1, MatchKind::Fallback),
+            (&["files", "new"             ], FILES_NEW         , &[                  ], 2, MatchKind::Exact   ),
+            (&["files", "new", "draft"    ], FILES_NEW_DRAFT   , &[                  ], 3, MatchKind::Exact   ),
+            (&["files", "42"              ], FILES_ID          , &[("id", "42")      ], 2, MatchKind::Exact   ),
+            (&["files", "readme"          ], FILES_NAME        , &[("name", "readme")], 2, MatchKind::Exact   ),
+            (&["files", "readme", "edit"  ], FILES_NAME_EDIT   , &[("name", "readme")], 3, MatchKind::Exact   ),
+            (&["files", "new", "edit"     ], FILES_NAME_EDIT   , &[("name", "new")   ], 3, MatchKind::Exact   ),
+            (&["files", "42", "edit"      ], FILES_NAME_EDIT   , &[("name", "42")    ], 3, MatchKind::Exact   ),
+            (&["files", "a", "b", "c"     ], FILES_PATH        , &[                  ], 4, MatchKind::Exact   ),
+            (&["docs", "api", "reference" ], DOCS_API_REFERENCE, &[                  ], 3, MatchKind::Exact   ),
+            (&["docs", "api", "intro"     ], DOCS_SECTION_INTRO, &[("section", "api")], 3, MatchKind::Exact   ),
+            (&["docs", "api"              ], BOTTOM_FALLBACK   , &[                  ], 2, MatchKind::Fallback),
+            (&["docs", "api", "DNE"       ], BOTTOM_FALLBACK   , &[                  ], 2, MatchKind::Fallback),
+            (&["a", "b", "c"              ], A_B_Y             , &[("y", "c")        ], 3, MatchKind::Exact   ),
+            (&["a", "z", "c"              ], A_X_C             , &[("x", "z")        ], 3, MatchKind::Exact   ),
+            (&["a", "b", "c", "d"         ], A_B_Y             , &[("y", "c")        ], 3, MatchKind::Prefix  ),
+            (&["DNE"                      ], BOTTOM_FALLBACK   , &[                  ], 0, MatchKind::Fallback),
+        ];
+        for (keys, val, params, keys_used, match_kind) in cases {
+            let found = rt.lookup(keys).unwrap();
+            let found_params = found.params.iter().map(|(name, raw, _)| (*name, *raw)).collect::<Vec<(&str, &str)>>();
+            assert_eq!((found.val, &found_params[..], found.keys_used, found.match_kind), (*val, *params, *keys_used, *match_kind), "{:?}", keys);
+        }
+        assert_eq!(rt.lookup(&["files", "a", "b", "c"]).unwrap().rest(), &["a", "b", "c"]);
+
+        // Nodes that only have method registrations rank like any other registered node
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.reg_method("GET", FILES_NEW, &[one("files"), one("new")]);
+        rt.register(FILES_NAME, &["files", ":name"]);
+        rt.register(DOCS_API_REFERENCE, &["static", "css"]);
+        rt.reg_method("GET", FILES_PATH, &[one("static"), one("*path")]);
+        assert_eq!(rt.lookup_method("GET", &["files", "new"]).unwrap().1, MethodMatch::Handler(FILES_NEW));
+        assert_eq!(rt.lookup_method("GET", &["files", "readme"]).unwrap().1, MethodMatch::Handler(FILES_NAME));
+        assert_eq!(rt.lookup_method("GET", &["static", "css", "x"]).unwrap().1, MethodMatch::Handler(FILES_PATH));
+        assert_eq!(rt.lookup_method("GET", &["static", "css"]).unwrap().1, MethodMatch::Handler(DOCS_API_REFERENCE));
+    }
+
+    #[test]
+    fn subtree_fallbacks() {
+        fn val(rt: &RoutingTable<i32>, keys: &[&str]) -> (i32, MatchKind) { let found = rt.lookup(keys).unwrap(); (*found.val(), found.match_kind()) }
+        const API_NOT_FOUND: &i32 = &50;
+        const V4_NOT_FOUND: &i32 = &51;
+        const APP_API: &i32 = &52;
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNUP, &["api", "v4", "sign-up"]);
+        rt.register(APP_USERS_ID     , &["api", "v4", "users", ":id"]);
+        rt.set_fallback(&["api"], API_NOT_FOUND);
+        rt.set_fallback(&["api", "v4"], V4_NOT_FOUND);
+
+        assert_eq!(val(&rt, &["api", "v4", "sign-up"      ]), (15, MatchKind::Exact   ));
+        assert_eq!(val(&rt, &["api", "v4", "DNE"          ]), (51, MatchKind::Fallback));
+        assert_eq!(val(&rt, &["api", "v4", "users"        ]), (51, MatchKind::Fallback));
+        assert_eq!(val(&rt, &["api", "v3"                 ]), (50, MatchKind::Fallback));
+        assert_eq!(val(&rt, &["api"                       ]), (50, MatchKind::Fallback));
+        assert_eq!(val(&rt, &["DNE"                       ]), (14, MatchKind::Fallback));
+
+        // A registered node keeps its own data but hands the fallback to whatever is under it
+        rt.register(APP_API, &["api"]);
+        assert_eq!(val(&rt, &["api"                       ]), (52, MatchKind::Exact   ));
+        assert_eq!(val(&rt, &["api", "v3"                 ]), (50, MatchKind::Fallback));
+        rt.register(APP_API_V4_SIGNIN, &["api", "v3", "sign-in"]);
+        assert_eq!(val(&rt, &["api", "v3"                 ]), (50, MatchKind::Fallback));
+        rt.unregister(&["api"]);
+        assert_eq!(val(&rt, &["api"                       ]), (50, MatchKind::Fallback));
+
+        // Removing every route under a prefix keeps the layer that holds its fallback
+        rt.unregister(&["api", "v4", "sign-up"]);
+        rt.remove_subtree(&["api", "v4", "users"]);
+        assert_eq!(val(&rt, &["api", "v4", "sign-up"      ]), (51, MatchKind::Fallback));
+        assert_eq!(rt.render_tree(), concat!(
+            "/ => 14\n",
+            "└── api (fallback 50)\n",
+            "    ├── v3\n",
+            "    │   └── sign-in => 16\n",
+            "    └── v4 (fallback 51)\n",
+        ));
+
+        match rt.try_set_fallback(&["api"], V4_NOT_FOUND) {
+            Err(RegistrationError { kind: RegistrationErrorKind::Duplicate(existing), .. }) => assert_eq!(*existing, 50),
+            _ => panic!("expected a duplicate fallback"),
+        }
+        rt.set_fallback(&[], API_NOT_FOUND);
+        assert_eq!(val(&rt, &[                            ]), (14, MatchKind::Fallback));
+        assert_eq!(val(&rt, &["DNE"                       ]), (50, MatchKind::Fallback));
+    }
+
+    #[test]
+    fn layered_lookup() {
+        const AUTH: &i32 = &53;
+        const RATE_LIMIT: &i32 = &54;
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(AUTH             , &["api"]);
+        rt.register(RATE_LIMIT       , &["api", "v4"]);
+        rt.register(APP_API_V4_SIGNUP, &["api", "v4", "sign-up"]);
+        rt.register(APP_USERS_ID     , &["api", "users", "{id:u64}"]);
+        rt.register(APP_USERS_ME     , &["api", "users", ":name", "me"]);
+
+        let layers = |keys: &'static [&'static str]| rt.lookup_layers(keys).map(|(_, layers)| layers.into_iter().copied().collect::<Vec<i32>>()).unwrap();
+        assert_eq!(layers(&["api", "v4", "sign-up"   ]), vec![14, 53, 54, 15]);
+        assert_eq!(layers(&["api", "v4", "DNE"       ]), vec![14, 53, 54]);
+        assert_eq!(layers(&["api", "users", "42"     ]), vec![14, 53, 19]);
+        assert_eq!(layers(&["api", "users", "42", "me"]), vec![14, 53, 18]);
+        assert_eq!(layers(&["api", "users"           ]), vec![14, 53]);
+        assert_eq!(layers(&["DNE"                    ]), vec![14]);
+        assert_eq!(*rt.lookup_layers(&["api", "v4"]).unwrap().0.val(), 54);
+    }
+
+    #[test]
+    fn lookup_owned_segments() {
+        fn identity_decoder(s: &str) -> Result<String, ()> { Ok(String::from(s)) }
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_USERS_ID_POSTS, &["users", "{id:u64}", "posts"]);
+        rt.register(APP_STATIC        , &["static", "*path"]);
+
+        let url = ParsedUrl::parse_new("/users/42/posts?page=2", identity_decoder).unwrap();
+        let found = rt.lookup_url(&url).unwrap();
+        assert_eq!((*found.val(), found.param("id"), found.typed("id")), (20, Some("42"), Some(ParamValue::U64(42))));
+
+        let keys = "static/css/site.css".split('/').map(String::from).collect::<Vec<String>>();
+        let found = rt.lookup(&keys).unwrap();
+        assert_eq!((*found.val(), found.rest()), (22, &keys[1..]));
+        let keys = vec![Cow::Borrowed("users"), Cow::Owned(String::from("42")), Cow::Borrowed("posts")];
+        assert_eq!(rt.lookup_method("GET", &keys).unwrap().1, MethodMatch::Handler(APP_USERS_ID_POSTS));
+    }
+
+    #[test]
+    fn slash_policy_routes() {
+        fn identity_decoder(s: &str) -> Result<String, ()> { Ok(String::from(s)) }
+        fn parse(raw_url: &str, policy: SlashPolicy) -> ParsedUrl { ParsedUrl::parse_with_policy(raw_url, identity_decoder, policy).unwrap() }
+
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNUP, &["api", "v4"]);
+        rt.register(APP_API_V4_SIGNIN, &["api", "v4", ""]);
+        rt.register(APP_USERS_ID     , &["", "users"]);
+        let url = parse("/api/v4/", SlashPolicy::Strict);
+        assert_eq!((rt.lookup_url(&url).unwrap().val, rt.lookup_url(&url).unwrap().match_kind), (APP_API_V4_SIGNIN, MatchKind::Exact));
+        assert_eq!(rt.lookup_url(&parse("//users", SlashPolicy::Strict)).unwrap().val, APP_USERS_ID);
+        assert_eq!(rt.lookup_url(&parse("/api/v4", SlashPolicy::Strict)).unwrap().val, APP_API_V4_SIGNUP);
+
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.set_slash_policy(SlashPolicy::Collapse);
+        rt.register(APP_API_V4_SIGNUP, &["api", "v4"]);
+        rt.register(APP_API_V4_SIGNIN, &["api", "v4", ""]);
+        assert!(matches!(rt.try_register(APP_USERS_ID, &["", "users"]).unwrap_err().kind, RegistrationErrorKind::EmptySegment));
+        assert!(matches!(rt.try_register(APP_USERS_ID, &["api", "v4", "", "users"]).unwrap_err().kind, RegistrationErrorKind::EmptySegment));
+        assert_eq!(rt.lookup_url(&parse("//api//v4//", SlashPolicy::Collapse)).unwrap().val, APP_API_V4_SIGNIN);
+        assert_eq!(rt.lookup_url(&parse("//api/v4", SlashPolicy::Collapse)).unwrap().val, APP_API_V4_SIGNUP);
+
+        let mut sub = RoutingTable::new(BOTTOM_FALLBACK);
+        sub.register(APP_USERS_ID, &["users", ""]);
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.set_slash_policy(SlashPolicy::Redirect);
+        assert!(matches!(rt.try_mount(&["api"], sub).unwrap_err().kind, RegistrationErrorKind::EmptySegment));
+    }
+
+    #[test]
+    #[should_panic]
+    fn slash_policy_mismatch_panic() {
+        fn identity_decoder(s: &str) -> Result<String, ()> { Ok(String::from(s)) }
+        let rt_panic = RoutingTable::new(BOTTOM_FALLBACK);
+        rt_panic.lookup_url(&ParsedUrl::parse_with_policy("/api/v4/", identity_decoder, SlashPolicy::Ignore).unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    fn slash_policy_unreachable_panic() {
+        let mut rt_panic = RoutingTable::new(BOTTOM_FALLBACK);
+        rt_panic.register(APP_USERS_ID, &["", "users"]);
+        rt_panic.set_slash_policy(SlashPolicy::Collapse);
+    }
+
+    #[test]
+    fn did_you_mean() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNUP , &["api", "v4", "sign-up" ]);
+        rt.register(APP_API_V4_SIGNIN , &["api", "v4", "sign-in" ]);
+        rt.register(APP_API_V4_SIGNOUT, &["api", "v4", "sign-out"]);
+        rt.register(APP_USERS_ID      , &["api", "v4", "users", ":id"]);
+
+        let found = rt.lookup(&["api", "v4", "sign-un"]).unwrap();
+        assert_eq!((found.match_kind(), found.missed()), (MatchKind::Fallback, Some("sign-un")));
+        assert_eq!(found.suggestions(2), vec!["sign-in", "sign-up"]);
+        assert_eq!(found.suggestions(5), vec!["sign-in", "sign-up", "sign-out"]);
+        assert_eq!(rt.lookup(&["api", "v5", "sign-up"]).unwrap().suggestions(5), vec!["v4"]);
+        assert_eq!(rt.lookup(&["api", "v4", "zzz"]).unwrap().suggestions(5), Vec::<&str>::new());
+        assert_eq!(rt.lookup(&["api", "v4"]).unwrap().suggestions(5), Vec::<&str>::new());
+        assert_eq!(rt.lookup(&["api", "v4", "sign-up"]).unwrap().missed(), None);
+
+        // Prefixes that only hold a fallback don't lead anywhere
+        rt.set_fallback(&["api", "v4", "sign-on"], BOTTOM_FALLBACK);
+        assert_eq!(rt.lookup(&["api", "v4", "sign-un"]).unwrap().suggestions(5), vec!["sign-in", "sign-up", "sign-out"]);
+        assert_eq!((edit_distance("sign-un", "sign-up"), edit_distance("", "abc"), edit_distance("kitten", "sitting")), (1, 3, 3));
+    }
+}
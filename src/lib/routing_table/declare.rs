@@ -0,0 +1,127 @@
+use super::OneOrMore;
+
+// routing_table! { FALLBACK;
+//     [GET|POST] "/api/v4/sign-up" => SIGNUP,
+//     "/users/:id" => USERS_ID,
+// }
+// builds a RoutingTable with FALLBACK at the root. Routes with methods go through reg_method, one
+// registration per method, the others through reg_more. Declaring the same literal route twice (with
+// overlapping methods) fails to compile, anything only decidable against the table still panics at runtime
+#[macro_export]
+macro_rules! routing_table {
+    ($fallback:expr; $( $([$($method:ident)|+])? $path:literal => $entity:expr ),* $(,)?) => {{
+        const _: () = assert!(!$crate::lib::routing_table::has_duplicate_routes(&[ $( (&[ $($(stringify!($method)),+)? ], $path) ),* ]), "routing_table! declares the same route twice");
+        let mut rt = $crate::lib::routing_table::RoutingTable::new($fallback);
+        $(
+            let route = $crate::lib::routing_table::split_route_path($path);
+            let methods: &[&str] = &[ $($(stringify!($method)),+)? ];
+            if methods.len() == 0 { rt.reg_more($entity, &route) }
+            for method in methods { rt.reg_method(method, $entity, &route) }
+        )*
+        rt
+    }};
+}
+
+// Used by routing_table!, "/api/v4/sign-up" becomes one("api"), one("v4"), one("sign-up")
+pub fn split_route_path(path: &'static str) -> Vec<OneOrMore<'static>> {
+    path.strip_prefix('/').unwrap_or(path).split('/').filter(|key| key.len() > 0).map(OneOrMore::One).collect()
+}
+
+// Used by routing_table! at compile time. Two declarations collide when their paths split into the
+// same keys and either both have no methods or they share one
+pub const fn has_duplicate_routes(routes: &[(&[&str], &str)]) -> bool {
+    let mut i = 0;
+    while i < routes.len() {
+        let mut j = i+1;
+        while j < routes.len() {
+            if same_path(routes[i].1, routes[j].1) && methods_overlap(routes[i].0, routes[j].0) { return true }
+            j += 1;
+        }
+        i += 1;
+    }
+    false
+}
+
+const fn methods_overlap(methods_a: &[&str], methods_b: &[&str]) -> bool {
+    if methods_a.len() == 0 && methods_b.len() == 0 { return true }
+    let mut i = 0;
+    while i < methods_a.len() {
+        let mut j = 0;
+        while j < methods_b.len() {
+            if str_eq(methods_a[i], methods_b[j]) { return true }
+            j += 1;
+        }
+        i += 1;
+    }
+    false
+}
+
+// Key by key like split_route_path splits them, so "/a/b", "a/b/" and "a//b" are the same path
+const fn same_path(path_a: &str, path_b: &str) -> bool {
+    let (bytes_a, bytes_b) = (path_a.as_bytes(), path_b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    loop {
+        while i < bytes_a.len() && bytes_a[i] == b'/' { i += 1 }
+        while j < bytes_b.len() && bytes_b[j] == b'/' { j += 1 }
+        if i == bytes_a.len() || j == bytes_b.len() { return i == bytes_a.len() && j == bytes_b.len() }
+        while i < bytes_a.len() && bytes_a[i] != b'/' && j < bytes_b.len() && bytes_b[j] != b'/' {
+            if bytes_a[i] != bytes_b[j] { return false }
+            i += 1;
+            j += 1;
+        }
+        let key_a_ended = i == bytes_a.len() || bytes_a[i] == b'/';
+        let key_b_ended = j == bytes_b.len() || bytes_b[j] == b'/';
+        if !key_a_ended || !key_b_ended { return false }
+    }
+}
+
+const fn str_eq(str_a: &str, str_b: &str) -> bool {
+    let (bytes_a, bytes_b) = (str_a.as_bytes(), str_b.as_bytes());
+    if bytes_a.len() != bytes_b.len() { return false }
+    let mut i = 0;
+    while i < bytes_a.len() {
+        if bytes_a[i] != bytes_b[i] { return false }
+        i += 1;
+    }
+    true
+}
+
+mod test {
+
+    use super::has_duplicate_routes;
+    use super::super::{MethodMatch, RoutingTable};
+
+    const BOTTOM_FALLBACK: &i32 = &14;
+    const APP_API_V4_SIGNUP: &i32 = &15;
+    const APP_API_V4_SIGNIN: &i32 = &16;
+    const APP_USERS_ID: &i32 = &19;
+
+    #[test]
+    fn declare_routes() {
+        let rt: RoutingTable<i32> = routing_table! { BOTTOM_FALLBACK;
+            [GET|POST] "/api/v4/sign-up" => APP_API_V4_SIGNUP,
+            [POST]     "/api/v4/sign-in" => APP_API_V4_SIGNIN,
+            [GET]      "/api/v4/sign-in" => APP_API_V4_SIGNUP,
+            "/users/{id:u64}"            => APP_USERS_ID,
+        };
+
+        assert_eq!(rt.lookup_method("POST", &["api", "v4", "sign-up"]).unwrap().1, MethodMatch::Handler(APP_API_V4_SIGNUP));
+        assert_eq!(rt.lookup_method("POST", &["api", "v4", "sign-in"]).unwrap().1, MethodMatch::Handler(APP_API_V4_SIGNIN));
+        assert_eq!(rt.lookup_method("PUT", &["api", "v4", "sign-in"]).unwrap().1, MethodMatch::NotAllowed(vec!["POST", "GET", "HEAD", "OPTIONS"]));
+        assert_eq!(rt.lookup(&["users", "42"]).unwrap().val, APP_USERS_ID);
+        assert_eq!(rt.lookup(&["users"]).unwrap().val, BOTTOM_FALLBACK);
+    }
+
+    // routing_table! feeds its declarations to this in a const, so a true here is a compile error there
+    #[test]
+    fn duplicate_declarations() {
+        assert!(!has_duplicate_routes(&[(&["GET"], "/a"), (&["POST"], "/a"), (&[], "/a"), (&["GET"], "/b")]));
+        assert!(has_duplicate_routes(&[(&["GET", "POST"], "/a"), (&["PUT", "POST"], "/a")]));
+        assert!(has_duplicate_routes(&[(&[], "/users/:id"), (&["GET"], "/b"), (&[], "/users/:id")]));
+        assert!(!has_duplicate_routes(&[(&[], "/users/:id"), (&[], "/users/:user")]));
+        assert!(has_duplicate_routes(&[(&[], "/a/b"), (&[], "a/b/")]));
+        assert!(has_duplicate_routes(&[(&["GET"], "/a//b"), (&["GET"], "/a/b")]));
+        assert!(!has_duplicate_routes(&[(&[], "/a/b"), (&[], "/a/bc"), (&[], "/ab"), (&[], "/a/b/c"), (&[], "/")]));
+        assert!(has_duplicate_routes(&[(&[], "/"), (&[], "")]));
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use serde_json::{json, Value};
+use super::{RoutingTable, RouteEntry};
+
+// Routes are told apart by path and method, added and removed ones come in the order routes() lists them
+#[derive(Debug)]
+pub struct RouteDiff<'s, T: Debug> {
+    pub added: Vec<RouteEntry<'s, T>>,
+    pub removed: Vec<RouteEntry<'s, T>>,
+    pub changed: Vec<RouteChange<'s, T>>,
+    pub fallback_changed: Vec<RouteChange<'s, T>>,
+}
+
+#[derive(Debug)]
+pub struct RouteChange<'s, T: Debug> {
+    pub route: Vec<String>,
+    pub method: Option<String>,
+    pub old: &'s T,
+    pub new: &'s T,
+}
+
+impl<'a, T: Debug + PartialEq> RoutingTable<'a, T> {
+
+    // What it takes to go from self to new. Besides the routes themselves this reports every
+    // level both tables have whose lookups that stop there with keys left over get something else,
+    // e.g. because a route was removed above it or a fallback was set. A registered level without
+    // a fallback of its own answers those with its entity, so it only shows up in changed
+    pub fn diff<'s>(self: &'s Self, new: &'s RoutingTable<'_, T>) -> RouteDiff<'s, T> {
+        let old_routes = self.routes().map(|entry| ((entry.route.clone(), entry.method.clone()), entry)).collect::<HashMap<_, _>>();
+        let new_keys = new.routes().map(|entry| (entry.route, entry.method)).collect::<HashSet<_>>();
+        let mut diff = RouteDiff { added: Vec::new(), removed: Vec::new(), changed: Vec::new(), fallback_changed: Vec::new() };
+        for entry in new.routes() {
+            match old_routes.get(&(entry.route.clone(), entry.method.clone())) {
+                None => diff.added.push(entry),
+                Some(old) if old.entity != entry.entity => diff.changed.push(RouteChange { route: entry.route, method: entry.method, old: old.entity, new: entry.entity }),
+                Some(_) => {}
+            }
+        }
+        diff.removed = self.routes().filter(|entry| !new_keys.contains(&(entry.route.clone(), entry.method.clone()))).collect();
+        self.diff_fallbacks(new, &mut Vec::new(), &mut diff.fallback_changed);
+        diff
+    }
+
+    fn diff_fallbacks<'s>(self: &'s Self, new: &'s RoutingTable<'_, T>, route: &mut Vec<String>, changed: &mut Vec<RouteChange<'s, T>>) -> () {
+        let (old_fallback, new_fallback) = (self.fallback.as_deref().unwrap_or(&self.data), new.fallback.as_deref().unwrap_or(&new.data));
+        let compared = self.depth == 0 || (!self.explicit && !new.explicit) || self.fallback.is_some() || new.fallback.is_some();
+        if compared && old_fallback != new_fallback {
+            changed.push(RouteChange { route: route.clone(), method: None, old: old_fallback, new: new_fallback });
+        }
+        let new_children = new.labeled_children();
+        for (key, child) in self.labeled_children() {
+            if let Some((_, new_child)) = new_children.iter().find(|(new_key, _)| *new_key == key) {
+                route.push(key);
+                child.diff_fallbacks(new_child, route, changed);
+                route.pop();
+            }
+        }
+    }
+}
+
+impl<'s, T: Debug> RouteDiff<'s, T> {
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.fallback_changed.is_empty()
+    }
+
+    // One line per difference: "+" added, "-" removed, "~" changed entity, "?" changed fallback
+    pub fn render_text(self: &Self) -> String {
+        let mut rendered = String::new();
+        for entry in &self.added {
+            rendered.push_str(&format!("+ {} => {:?}\n", describe(&entry.route, &entry.method), entry.entity));
+        }
+        for entry in &self.removed {
+            rendered.push_str(&format!("- {} => {:?}\n", describe(&entry.route, &entry.method), entry.entity));
+        }
+        for change in &self.changed {
+            rendered.push_str(&format!("~ {} => {:?} -> {:?}\n", describe(&change.route, &change.method), change.old, change.new));
+        }
+        for change in &self.fallback_changed {
+            rendered.push_str(&format!("? {} falls back to {:?} -> {:?}\n", describe(&change.route, &None), change.old, change.new));
+        }
+        rendered
+    }
+
+    pub fn to_json(self: &Self) -> Value {
+        let entries = |entries: &[RouteEntry<'s, T>]| entries.iter().map(|entry| json!({
+            "route": describe(&entry.route, &None),
+            "method": entry.method,
+            "entity": format!("{:?}", entry.entity),
+        })).collect::<Vec<Value>>();
+        let changes = |changes: &[RouteChange<'s, T>]| changes.iter().map(|change| json!({
+            "route": describe(&change.route, &None),
+            "method": change.method,
+            "old": format!("{:?}", change.old),
+            "new": format!("{:?}", change.new),
+        })).collect::<Vec<Value>>();
+        json!({
+            "added": entries(&self.added),
+            "removed": entries(&self.removed),
+            "changed": changes(&self.changed),
+            "fallback_changed": changes(&self.fallback_changed),
+        })
+    }
+}
+
+fn describe(route: &[String], method: &Option<String>) -> String {
+    let path = format!("/{}", route.join("/"));
+    match method {
+        Some(method) => format!("{} {}", method, path),
+        None => path,
+    }
+}
+
+mod test {
+
+    use serde_json::json;
+    use super::super::{RoutingTable, one};
+
+    const BOTTOM_FALLBACK: &i32 = &14;
+    const APP_API_V4_SIGNUP: &i32 = &15;
+    const APP_API_V4_SIGNIN: &i32 = &16;
+    const APP_API_V4_SIGNOUT: &i32 = &17;
+    const APP_USERS_ID: &i32 = &19;
+    const API_NOT_FOUND: &i32 = &50;
+
+    #[test]
+    fn diff_tables() {
+        let mut old = RoutingTable::new(BOTTOM_FALLBACK);
+        old.register(APP_API_V4_SIGNUP, &["api", "v4", "sign-up"]);
+        old.register(APP_API_V4_SIGNIN, &["api", "v4", "sign-in"]);
+        old.register(APP_USERS_ID     , &["users", ":id"]);
+        old.register(APP_USERS_ID     , &["api"]);
+        old.reg_method("POST", APP_API_V4_SIGNOUT, &[one("api"), one("v4"), one("sign-out")]);
+
+        let mut new = RoutingTable::new(BOTTOM_FALLBACK);
+        new.register(APP_API_V4_SIGNUP , &["api", "v4", "sign-up"]);
+        new.register(APP_API_V4_SIGNOUT, &["api", "v4", "sign-in"]);
+        new.register(APP_USERS_ID      , &["users", ":id"]);
+        new.register(APP_USERS_ID      , &["users", ":id", "posts"]);
+        new.set_fallback(&["users"], API_NOT_FOUND);
+
+        assert!(old.diff(&old).is_empty());
+        let diff = old.diff(&new);
+        assert_eq!(diff.render_text(), concat!(
+            "+ /users/:id/posts => 19\n",
+            "- /api => 19\n",
+            "- POST /api/v4/sign-out => 17\n",
+            "~ /api/v4/sign-in => 16 -> 17\n",
+            "? /api/v4 falls back to 19 -> 14\n",
+            "? /users falls back to 14 -> 50\n",
+        ));
+        assert_eq!(diff.to_json()["changed"], json!([{ "route": "/api/v4/sign-in", "method": null, "old": "16", "new": "17" }]));
+        assert_eq!(diff.to_json()["removed"][1], json!({ "route": "/api/v4/sign-out", "method": "POST", "entity": "17" }));
+
+        // The other way round /api is registered again, so /api/v4 falls back to it instead of the root
+        let diff = new.diff(&old);
+        assert_eq!(diff.fallback_changed.iter().map(|change| (change.route.join("/"), *change.old, *change.new)).collect::<Vec<(String, i32, i32)>>(), vec![
+            (String::from("api/v4"), 14, 19),
+            (String::from("users"), 50, 14),
+        ]);
+    }
+}
@@ -0,0 +1,235 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use super::{RoutingTable, RTLookupResult, Constraint, ParamValue, MatchKind};
+
+// Read-only copy of a finished RoutingTable with every node in one array, built once and then
+// independent of the table, so it can outlive it. Each node's static keys get a perfect hash (hash
+// and displace, as in CHD): a key's bucket picks a displacement pair and that gives its one possible
+// slot, so a lookup hashes once and compares one key per level. Typed, param and catch-all children
+// are kept per node and tried in the RoutingTable's order, so the results are exactly the table's
+pub struct FrozenRoutingTable<'a, T: Debug> {
+    nodes: Box<[FrozenNode<'a, T>]>,
+    slots: Box<[Option<(Cow<'a, str>, u32)>]>,
+    displacements: Box<[(u32, u32)]>,
+}
+
+// slots[from..to] hold the node's keys with the index of the child each one leads to. The leaf is
+// the node's own copy of the table node, whose data, fallback and methods lookups are answered from
+struct FrozenNode<'a, T: Debug> {
+    leaf: RoutingTable<'a, T>,
+    from: u32,
+    to: u32,
+    displacements_from: u32,
+    displacements_to: u32,
+    seed: u64,
+    typed: Box<[(Cow<'a, str>, Constraint<'a>, u32)]>,
+    param: Option<(Cow<'a, str>, u32)>,
+    catch_all: Option<(Cow<'a, str>, u32)>,
+}
+
+// Keys per displacement bucket, and slots per key
+const BUCKET_SIZE: usize = 4;
+const SLOTS_PER_KEY: usize = 2;
+
+impl<'a, T: Debug> FrozenRoutingTable<'a, T> {
+
+    pub fn new(rt: &RoutingTable<'a, T>) -> Self {
+        let mut nodes = Vec::new();
+        let mut slots = Vec::new();
+        let mut displacements = Vec::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(rt);
+        while let Some(source) = queue.pop_front() {
+            let (from, displacements_from) = (slots.len() as u32, displacements.len() as u32);
+            let mut seed = 0;
+            if source.map.len() > 0 {
+                let node_keys = source.map.keys().map(|key| key.as_ref()).collect::<Vec<&str>>();
+                let (found_seed, node_slots, node_displacements) = perfect_hash(&node_keys);
+                seed = found_seed;
+                displacements.extend(node_displacements);
+                for slot in node_slots {
+                    slots.push(slot.map(|key| (source.map.get_key_value(key).unwrap().0.clone(), (nodes.len() + 1 + queue.len()) as u32)));
+                    if let Some(key) = slot { queue.push_back(&source.map[key]) }
+                }
+            }
+            let mut enqueue = |child| {
+                queue.push_back(child);
+                (nodes.len() + queue.len()) as u32
+            };
+            let typed = source.typed.iter().map(|(name, constraint, typed_rt)| (name.clone(), constraint.clone(), enqueue(typed_rt))).collect();
+            let param = source.param.as_ref().map(|(name, param_rt)| (name.clone(), enqueue(param_rt)));
+            let catch_all = source.catch_all.as_ref().map(|(name, catch_all_rt)| (name.clone(), enqueue(catch_all_rt)));
+            let to = slots.len() as u32;
+            nodes.push(FrozenNode { leaf: source.leaf(), from, to, displacements_from, displacements_to: displacements.len() as u32, seed, typed, param, catch_all });
+        }
+        FrozenRoutingTable { nodes: nodes.into(), slots: slots.into(), displacements: displacements.into() }
+    }
+
+    pub fn lookup<'s, S: AsRef<str>>(self: &'s Self, keys: &'s [S]) -> Option<RTLookupResult<'s, T, S>> {
+        let found = self.lookup_core(&self.nodes[0], keys, 0, &mut Vec::new())?;
+        self.nodes[0].leaf.record_hit(&found);
+        Some(found)
+    }
+
+    // Same search as RoutingTable::lookup_core, with the children taken from the arrays
+    fn lookup_core<'s, S: AsRef<str>>(self: &'s Self, node: &'s FrozenNode<'a, T>, keys: &'s [S], start: usize, params: &mut Vec<(&'s str, &'s str, ParamValue)>) -> Option<RTLookupResult<'s, T, S>> {
+        let key = match keys.get(start) {
+            Some(key) => key.as_ref(),
+            None => return Some(node.leaf.stop_here(keys, start, params)),
+        };
+        let mut best: Option<RTLookupResult<'s, T, S>> = None;
+        let candidates = self.child(node, key).map(|child| (child, None)).into_iter()
+            .chain(node.typed.iter().filter_map(|(name, constraint, typed_child)| constraint.test(key).map(|value| (*typed_child as usize, Some((&**name, key, value))))))
+            .chain(node.param.iter().map(|(name, param_child)| (*param_child as usize, Some((&**name, key, ParamValue::Str)))));
+        let params_len = params.len();
+        for (child, param) in candidates {
+            params.extend(param);
+            let found = self.lookup_core(&self.nodes[child], keys, start+1, params)?;
+            if found.match_kind == MatchKind::Exact { return Some(found) }
+            params.truncate(params_len);
+            if best.as_ref().map_or(true, |best| found.match_kind.rank() < best.match_kind.rank()) { best = Some(found) }
+        }
+        if let Some((_, catch_all_child)) = &node.catch_all {
+            let found = self.nodes[*catch_all_child as usize].leaf.catch_all_here(keys, start, params);
+            if best.as_ref().map_or(true, |best| found.match_kind.rank() < best.match_kind.rank()) { best = Some(found) }
+        }
+        Some(best.unwrap_or_else(|| node.leaf.stop_here(keys, start, params)))
+    }
+
+    fn child(self: &Self, node: &FrozenNode<'a, T>, key: &str) -> Option<usize> {
+        let (from, to) = (node.from as usize, node.to as usize);
+        if from == to { return None }
+        let node_displacements = &self.displacements[node.displacements_from as usize..node.displacements_to as usize];
+        let (bucket, f1, f2) = hash(node.seed, key);
+        let (d1, d2) = node_displacements[bucket as usize % node_displacements.len()];
+        match &self.slots[from + (displace(f1, f2, d1, d2) % (to-from) as u32) as usize] {
+            Some((slot_key, child)) if slot_key == key => Some(*child as usize),
+            _ => None,
+        }
+    }
+}
+
+// Returns the seed, the slots and the displacement pair of every bucket
+fn perfect_hash<'k>(keys: &[&'k str]) -> (u64, Vec<Option<&'k str>>, Vec<(u32, u32)>) {
+    let slots_len = (keys.len() * SLOTS_PER_KEY) as u32;
+    let buckets_len = (keys.len() + BUCKET_SIZE - 1) / BUCKET_SIZE;
+    'seed: for seed in 0.. {
+        let hashes = keys.iter().map(|key| hash(seed, key)).collect::<Vec<(u32, u32, u32)>>();
+        let mut buckets = vec![Vec::new(); buckets_len];
+        for (index, (bucket, _, _)) in hashes.iter().enumerate() {
+            buckets[*bucket as usize % buckets_len].push(index);
+        }
+        let mut order = (0..buckets_len).collect::<Vec<usize>>();
+        order.sort_by_key(|bucket| std::cmp::Reverse(buckets[*bucket].len()));
+
+        let mut slots = vec![None; slots_len as usize];
+        let mut displacements = vec![(0, 0); buckets_len];
+        for bucket in order {
+            let slots_for = |d1, d2| buckets[bucket].iter().map(|index| (displace(hashes[*index].1, hashes[*index].2, d1, d2) % slots_len) as usize).collect::<Vec<usize>>();
+            let found = (0..slots_len).flat_map(|d1| (0..slots_len).map(move |d2| (d1, d2))).find(|(d1, d2)| {
+                let mut taken = slots_for(*d1, *d2);
+                taken.sort();
+                taken.dedup();
+                taken.len() == buckets[bucket].len() && taken.iter().all(|slot| slots[*slot].is_none())
+            });
+            let (d1, d2) = match found {
+                Some(displacement) => displacement,
+                None => continue 'seed,
+            };
+            for (index, slot) in buckets[bucket].iter().zip(slots_for(d1, d2)) {
+                slots[slot] = Some(keys[*index]);
+            }
+            displacements[bucket] = (d1, d2);
+        }
+        return (seed, slots, displacements)
+    }
+    unreachable!()
+}
+
+// FNV-1a, split into the bucket and the two values the displacements apply to
+fn hash(seed: u64, key: &str) -> (u32, u32, u32) {
+    let mut h = 0xcbf29ce484222325 ^ seed.wrapping_mul(0x9e3779b97f4a7c15);
+    for b in key.bytes() {
+        h = (h ^ b as u64).wrapping_mul(0x100000001b3);
+    }
+    let mixed = (h ^ (h >> 29)).wrapping_mul(0xbf58476d1ce4e5b9);
+    ((h >> 32) as u32, mixed as u32, (mixed >> 32) as u32)
+}
+
+fn displace(f1: u32, f2: u32, d1: u32, d2: u32) -> u32 {
+    f1.wrapping_add(f2.wrapping_mul(d1)).wrapping_add(d2)
+}
+
+mod test {
+
+    use test::Bencher;
+    use super::FrozenRoutingTable;
+    use super::super::{RoutingTable, MatchKind, ParamValue};
+    use super::super::index_test::{assert_same_results, generated_table, bench_lookups};
+
+    const BOTTOM_FALLBACK: &i32 = &14;
+    const APP_API_V4_SIGNUP: &i32 = &15;
+    const APP_API_V4_SIGNIN: &i32 = &16;
+    const APP_API_FALLBACK: &i32 = &17;
+    const APP_USERS_ME: &i32 = &18;
+    const APP_USERS_ID: &i32 = &19;
+    const APP_USERS_ID_POSTS: &i32 = &20;
+    const APP_FILES_NAME: &i32 = &21;
+    const APP_STATIC: &i32 = &22;
+
+    #[test]
+    fn same_results_as_hashmap_tree() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNUP , &["api", "v4", "sign-up"         ]);
+        rt.register(APP_API_V4_SIGNIN , &["api", "v4", "sign-in"         ]);
+        rt.register(APP_USERS_ME      , &["users", "me"                  ]);
+        rt.register(APP_USERS_ID      , &["users", "{id:u64}"            ]);
+        rt.register(APP_USERS_ID_POSTS, &["users", "{id:u64}", "posts"  ]);
+        rt.register(APP_STATIC        , &["users", "me", "*path"         ]);
+        rt.register(APP_FILES_NAME    , &["files", ":name"               ]);
+        rt.set_fallback(&["api"], APP_API_FALLBACK);
+        let frozen = FrozenRoutingTable::new(&rt);
+
+        let keys: &[&[&str]] = &[
+            &[], &["api"], &["api", "v4"], &["api", "v4", "sign-up"], &["api", "v4", "sign-in", "tail"], &["api", "v4", "DNE"],
+            &["users", "me"], &["users", "42"], &["users", "42", "posts"], &["users", "42", "DNE"], &["users", "me", "a", "b"],
+            &["users", "x"], &["files", "a.txt"], &["files", "a.txt", "b"], &["DNE"], &["a", "b", "c"],
+        ];
+        assert_same_results(&rt, keys, |keys| frozen.lookup(keys));
+        assert_eq!(frozen.lookup(&["users", "42"]).unwrap().match_kind(), MatchKind::Exact);
+        let dynamic_children = frozen.nodes.iter().map(|node| node.typed.len() + node.param.iter().count() + node.catch_all.iter().count()).sum::<usize>();
+        assert_eq!(frozen.nodes.len(), 1 + frozen.slots.iter().filter(|slot| slot.is_some()).count() + dynamic_children);
+    }
+
+    #[test]
+    fn outlives_the_table() {
+        let frozen = {
+            let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+            rt.register(APP_USERS_ID_POSTS, &["users", "{id:u64}", "posts"]);
+            FrozenRoutingTable::new(&rt)
+        };
+        let found = frozen.lookup(&["users", "42", "posts"]).unwrap();
+        assert_eq!((found.val, found.typed("id"), found.match_kind()), (APP_USERS_ID_POSTS, Some(ParamValue::U64(42)), MatchKind::Exact));
+        assert_eq!(frozen.lookup(&["users", "x"]).unwrap().val, BOTTOM_FALLBACK);
+    }
+
+    // A wide and shallow static route set, the shape most of our routes have
+    fn bench_frozen(b: &mut Bencher, routes: usize, frozen: bool) {
+        let (rt, samples) = generated_table(routes, |i| vec![format!("service{}", i % 50), format!("resource{}", i / 50 % 200), format!("action{}", i)]);
+        let rt_frozen = FrozenRoutingTable::new(&rt);
+        bench_lookups(b, &samples, |keys| if frozen { rt_frozen.lookup(keys) } else { rt.lookup(keys) }.map(|found| *found.val));
+    }
+
+    #[bench]
+    fn hashmap_tree_static_10k(b: &mut Bencher) { bench_frozen(b, 10_000, false) }
+
+    #[bench]
+    fn frozen_static_10k(b: &mut Bencher) { bench_frozen(b, 10_000, true) }
+
+    #[bench]
+    fn hashmap_tree_static_100k(b: &mut Bencher) { bench_frozen(b, 100_000, false) }
+
+    #[bench]
+    fn frozen_static_100k(b: &mut Bencher) { bench_frozen(b, 100_000, true) }
+}
@@ -0,0 +1,56 @@
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::mem::size_of;
+use test::{Bencher, black_box};
+use super::{RoutingTable, OwnedRoutingTable, RTLookupResult, Constraint, Entity};
+
+// Shared by the tests and benches of the read-only indexes built from a RoutingTable (radix and frozen),
+// which have to find exactly what the table itself finds
+
+// The indexes keep their own copies of the nodes, so keep_going is compared by what it holds
+pub(super) fn assert_same_results<'s, T: Debug>(rt: &'s RoutingTable<T>, keys: &'s [&'s [&'s str]], lookup: impl Fn(&'s [&'s str]) -> Option<RTLookupResult<'s, T>>) -> () {
+    for keys in keys {
+        let (expected, found) = (rt.lookup(keys).unwrap(), lookup(keys).unwrap());
+        assert!(std::ptr::eq(expected.val, found.val) && std::ptr::eq::<T>(&*expected.keep_going.data, &*found.keep_going.data), "{:?}", keys);
+        assert_eq!((expected.keep_going.explicit, expected.keep_going.methods.len(), expected.keep_going.fallback.is_some()), (found.keep_going.explicit, found.keep_going.methods.len(), found.keep_going.fallback.is_some()), "{:?}", keys);
+        assert_eq!((expected.depth, expected.keys_used, &expected.params, expected.rest, expected.missed, expected.match_kind), (found.depth, found.keys_used, &found.params, found.rest, found.missed, found.match_kind), "{:?}", keys);
+    }
+}
+
+// Registers route_of(i) to i for every i below routes, every thousandth route is kept as a lookup sample
+pub(super) fn generated_table(routes: usize, route_of: fn(usize) -> Vec<String>) -> (OwnedRoutingTable<usize>, Vec<Vec<String>>) {
+    let mut rt = OwnedRoutingTable::new(0);
+    let mut samples = Vec::new();
+    for i in 0..routes {
+        let route = route_of(i);
+        rt.register(i, &route.iter().map(|key| key.as_str()).collect::<Vec<&str>>());
+        if i % (routes / 1000) == 0 { samples.push(route) }
+    }
+    (rt, samples)
+}
+
+pub(super) fn bench_lookups(b: &mut Bencher, samples: &[Vec<String>], lookup: impl Fn(&[&str]) -> Option<usize>) -> () {
+    let samples = samples.iter().map(|route| route.iter().map(|key| key.as_str()).collect::<Vec<&str>>()).collect::<Vec<Vec<&str>>>();
+    b.iter(|| {
+        for keys in &samples {
+            black_box(lookup(keys).unwrap());
+        }
+    });
+}
+
+// Rough footprint of everything below rt: each child where its parent keeps it, a control byte per
+// hash table slot, and the keys the table owns. Entities are shared with the table, so they don't count
+pub(super) fn table_bytes<T: Debug>(rt: &RoutingTable<T>) -> usize {
+    rt.map.capacity() * (size_of::<(Cow<str>, RoutingTable<T>)>() + 1) + rt.map.keys().map(key_bytes).sum::<usize>()
+        + rt.typed.capacity() * size_of::<(Cow<str>, Constraint, RoutingTable<T>)>()
+        + rt.param.iter().chain(&rt.catch_all).map(|(name, _)| size_of::<RoutingTable<T>>() + key_bytes(name)).sum::<usize>()
+        + rt.methods.capacity() * size_of::<(Cow<str>, Entity<T>)>()
+        + rt.children().map(table_bytes).sum::<usize>()
+}
+
+pub(super) fn key_bytes(key: &Cow<str>) -> usize {
+    match key {
+        Cow::Owned(key) => key.capacity(),
+        Cow::Borrowed(_) => 0,
+    }
+}
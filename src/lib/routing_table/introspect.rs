@@ -0,0 +1,260 @@
+use std::fmt::Debug;
+use serde_json::{json, Value};
+use super::RoutingTable;
+
+#[derive(Debug)]
+pub struct RouteEntry<'s, T: Debug> {
+    pub route: Vec<String>,
+    pub method: Option<String>,
+    pub entity: &'s T,
+    pub depth: usize,
+}
+
+// Depth first, children in the order lookup tries them (static keys sorted alphabetically)
+pub struct Routes<'s, 'a, T: Debug> {
+    stack: Vec<(Vec<String>, &'s RoutingTable<'a, T>)>,
+    pending: Vec<RouteEntry<'s, T>>,
+}
+
+impl<'a, T: Debug> RoutingTable<'a, T> {
+
+    // Every explicitly registered route and then every method registered on it, the root fallback isn't one of them
+    // but methods registered for the empty route are
+    pub fn routes(self: &Self) -> Routes<'_, 'a, T> {
+        let mut routes = Routes { stack: Vec::new(), pending: Vec::new() };
+        routes.push_children(&[], self);
+        for (method, entity) in self.methods.iter().rev() {
+            routes.pending.push(RouteEntry { route: Vec::new(), method: Some(method.to_string()), entity, depth: self.depth });
+        }
+        routes
+    }
+
+    pub fn render_tree(self: &Self) -> String {
+        let mut rendered = format!("/ => {:?}", *self.data);
+        if let Some(fallback) = &self.fallback { rendered.push_str(&format!(" (fallback {:?})", **fallback)) }
+        rendered.push('\n');
+        self.render_children("", &mut rendered);
+        rendered
+    }
+
+    pub fn to_json(self: &Self) -> Value {
+        self.to_json_core("")
+    }
+
+    // Graphviz source. Registered nodes are solid boxes, implicit layers dashed and gray with the
+    // entity they inherit in parentheses. Keys that one OneOrMore::More (e.g. from reg_parallel)
+    // branched into hang off a shared point, so a fan-out shows up as one fork instead of loose edges
+    pub fn to_dot(self: &Self) -> String {
+        let mut dot = String::from("digraph routes {\n    node [shape=box, fontname=monospace];\n");
+        self.to_dot_core("/", &mut 0, &mut dot);
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn to_dot_core(self: &Self, key: &str, next_id: &mut usize, dot: &mut String) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let mut label = vec![key.to_string(), format!("depth {}", self.depth)];
+        label.push(if self.explicit { format!("{:?}", *self.data) } else { format!("({:?})", *self.data) });
+        if let Some(fallback) = &self.fallback { label.push(format!("fallback {:?}", **fallback)) }
+        label.extend(self.methods.iter().map(|(method, entity)| format!("{} => {:?}", method, **entity)));
+        let style = if self.explicit { "solid" } else { "dashed, color=gray50, fontcolor=gray50" };
+        let label = label.iter().map(|line| dot_escape(line)).collect::<Vec<String>>().join("\\n");
+        dot.push_str(&format!("    n{} [label=\"{}\", style={}];\n", id, label, style));
+
+        let children = self.labeled_children();
+        let mut forks = Vec::new();
+        for fan_out in &self.fan_outs {
+            let present = fan_out.iter().filter(|key| children.iter().any(|(child_key, _)| child_key == key.as_ref())).count();
+            if present > 1 {
+                let fork = *next_id;
+                *next_id += 1;
+                dot.push_str(&format!("    n{} [shape=point];\n    n{} -> n{} [arrowhead=none];\n", fork, id, fork));
+                forks.push((fork, fan_out));
+            }
+        }
+        for (child_key, child) in &children {
+            let child_id = child.to_dot_core(child_key, next_id, dot);
+            let from = forks.iter().find(|(_, fan_out)| fan_out.iter().any(|key| key == child_key)).map_or(id, |(fork, _)| *fork);
+            dot.push_str(&format!("    n{} -> n{};\n", from, child_id));
+        }
+        id
+    }
+
+    pub(super) fn labeled_children(self: &Self) -> Vec<(String, &RoutingTable<'a, T>)> {
+        let mut literals = self.map.iter().map(|(key, child)| (key.to_string(), child)).collect::<Vec<(String, &RoutingTable<'a, T>)>>();
+        literals.sort_by(|(key_a, _), (key_b, _)| key_a.cmp(key_b));
+        literals.into_iter()
+            .chain(self.typed.iter().map(|(name, constraint, typed_rt)| (format!("{{{}:{}}}", name, constraint.kind()), typed_rt)))
+            .chain(self.param.iter().map(|(name, param_rt)| (format!(":{}", name), &**param_rt)))
+            .chain(self.catch_all.iter().map(|(name, catch_all_rt)| (format!("*{}", name), &**catch_all_rt)))
+            .collect()
+    }
+
+    // Implicit layers are printed without an entity, they only fall back to their parent's
+    fn render_children(self: &Self, indent: &str, rendered: &mut String) -> () {
+        let children = self.labeled_children();
+        for (index, (key, child)) in children.iter().enumerate() {
+            let last = index+1 == children.len();
+            rendered.push_str(&format!("{}{}{}", indent, if last { "└── " } else { "├── " }, key));
+            if child.explicit { rendered.push_str(&format!(" => {:?}", *child.data)) }
+            if let Some(fallback) = &child.fallback { rendered.push_str(&format!(" (fallback {:?})", **fallback)) }
+            if child.methods.len() > 0 {
+                let methods = child.methods.iter().map(|(method, entity)| format!("{} => {:?}", method, **entity)).collect::<Vec<String>>();
+                rendered.push_str(&format!(" [{}]", methods.join(", ")));
+            }
+            rendered.push('\n');
+            child.render_children(&format!("{}{}", indent, if last { "    " } else { "│   " }), rendered);
+        }
+    }
+
+    // "fallback" only shows up on nodes that have one
+    fn to_json_core(self: &Self, key: &str) -> Value {
+        let mut exported = json!({
+            "key": key,
+            "explicit": self.explicit,
+            "depth": self.depth,
+            "entity": format!("{:?}", *self.data),
+            "methods": self.methods.iter().map(|(method, entity)| (method.to_string(), Value::from(format!("{:?}", **entity)))).collect::<serde_json::Map<String, Value>>(),
+            "children": self.labeled_children().iter().map(|(key, child)| child.to_json_core(key)).collect::<Vec<Value>>(),
+        });
+        if let Some(fallback) = &self.fallback { exported["fallback"] = Value::from(format!("{:?}", **fallback)) }
+        exported
+    }
+}
+
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<'s, 'a, T: Debug> Routes<'s, 'a, T> {
+    fn push_children(self: &mut Self, route: &[String], rt: &'s RoutingTable<'a, T>) -> () {
+        for (key, child) in rt.labeled_children().into_iter().rev() {
+            let mut full_route = route.to_vec();
+            full_route.push(key);
+            self.stack.push((full_route, child));
+        }
+    }
+}
+
+impl<'s, 'a, T: Debug> Iterator for Routes<'s, 'a, T> {
+    type Item = RouteEntry<'s, T>;
+    fn next(self: &mut Self) -> Option<Self::Item> {
+        while self.pending.len() == 0 {
+            let (route, rt) = self.stack.pop()?;
+            self.push_children(&route, rt);
+            for (method, entity) in rt.methods.iter().rev() {
+                self.pending.push(RouteEntry { route: route.clone(), method: Some(method.to_string()), entity, depth: rt.depth });
+            }
+            if rt.explicit {
+                self.pending.push(RouteEntry { route, method: None, entity: &rt.data, depth: rt.depth });
+            }
+        }
+        self.pending.pop()
+    }
+}
+
+mod test {
+
+    use serde_json::json;
+    use super::super::{RoutingTable, more, one, ser, par};
+
+    const BOTTOM_FALLBACK: &i32 = &14;
+    const APP_API_V4_SIGNUP: &i32 = &15;
+    const APP_API_V4_SIGNIN: &i32 = &16;
+    const APP_USERS_ID: &i32 = &19;
+    const APP_USERS_ID_POSTS: &i32 = &20;
+    const APP_STATIC: &i32 = &22;
+
+    fn sample_table() -> RoutingTable<'static, i32> {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.reg_more(APP_API_V4_SIGNUP, &[more(&["POST", "GET"]), one("api"), one("v4"), one("sign-up")]);
+        rt.register(APP_API_V4_SIGNIN , &["POST", "api", "v4", "sign-in"]);
+        rt.register(APP_USERS_ID      , &["users", "{id:u64}"          ]);
+        rt.register(APP_USERS_ID_POSTS, &["users", "{id:u64}", "posts" ]);
+        rt.register(APP_STATIC        , &["users", "*path"             ]);
+        rt
+    }
+
+    #[test]
+    fn enumerate_routes() {
+        let rt = sample_table();
+        let routes = rt.routes().map(|entry| (entry.route.join("/"), *entry.entity, entry.depth)).collect::<Vec<(String, i32, usize)>>();
+        assert_eq!(routes, vec![
+            (String::from("GET/api/v4/sign-up"   ), 15, 4),
+            (String::from("POST/api/v4/sign-in"  ), 16, 4),
+            (String::from("POST/api/v4/sign-up"  ), 15, 4),
+            (String::from("users/{id:u64}"       ), 19, 2),
+            (String::from("users/{id:u64}/posts" ), 20, 3),
+            (String::from("users/*path"          ), 22, 2),
+        ]);
+        assert_eq!(RoutingTable::new(BOTTOM_FALLBACK).routes().count(), 0);
+    }
+
+    #[test]
+    fn render_and_export() {
+        let rt = sample_table();
+        assert_eq!(rt.render_tree(), concat!(
+            "/ => 14\n",
+            "├── GET\n",
+            "│   └── api\n",
+            "│       └── v4\n",
+            "│           └── sign-up => 15\n",
+            "├── POST\n",
+            "│   └── api\n",
+            "│       └── v4\n",
+            "│           ├── sign-in => 16\n",
+            "│           └── sign-up => 15\n",
+            "└── users\n",
+            "    ├── {id:u64} => 19\n",
+            "    │   └── posts => 20\n",
+            "    └── *path => 22\n",
+        ));
+
+        let exported = rt.to_json();
+        assert_eq!(exported["explicit"], json!(true));
+        assert_eq!(exported["entity"], json!("14"));
+        let users = &exported["children"][2];
+        assert_eq!(users["key"], json!("users"));
+        assert_eq!(users["explicit"], json!(false));
+        assert_eq!(users["entity"], json!("14"));
+        assert_eq!(users["children"][0], json!({
+            "key": "{id:u64}", "explicit": true, "depth": 2, "entity": "19",
+            "methods": {},
+            "children": [{ "key": "posts", "explicit": true, "depth": 3, "entity": "20", "methods": {}, "children": [] }],
+        }));
+    }
+
+    #[test]
+    fn dot_export() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.reg_parallel(APP_API_V4_SIGNUP, &[ser(&["api", "v4"]), par(&["sign-up", "register"])]);
+        rt.register(APP_API_V4_SIGNIN, &["api", "v4", "sign-in"]);
+        rt.register(APP_USERS_ID, &["users", "{id:u64}"]);
+        rt.reg_method("GET", APP_STATIC, &[one("users")]);
+        assert_eq!(rt.to_dot(), concat!(
+            "digraph routes {\n",
+            "    node [shape=box, fontname=monospace];\n",
+            "    n0 [label=\"/\\ndepth 0\\n14\", style=solid];\n",
+            "    n1 [label=\"api\\ndepth 1\\n(14)\", style=dashed, color=gray50, fontcolor=gray50];\n",
+            "    n2 [label=\"v4\\ndepth 2\\n(14)\", style=dashed, color=gray50, fontcolor=gray50];\n",
+            "    n3 [shape=point];\n",
+            "    n2 -> n3 [arrowhead=none];\n",
+            "    n4 [label=\"register\\ndepth 3\\n15\", style=solid];\n",
+            "    n3 -> n4;\n",
+            "    n5 [label=\"sign-in\\ndepth 3\\n16\", style=solid];\n",
+            "    n2 -> n5;\n",
+            "    n6 [label=\"sign-up\\ndepth 3\\n15\", style=solid];\n",
+            "    n3 -> n6;\n",
+            "    n1 -> n2;\n",
+            "    n0 -> n1;\n",
+            "    n7 [label=\"users\\ndepth 1\\n(14)\\nGET => 22\", style=dashed, color=gray50, fontcolor=gray50];\n",
+            "    n8 [label=\"{id:u64}\\ndepth 2\\n19\", style=solid];\n",
+            "    n7 -> n8;\n",
+            "    n0 -> n7;\n",
+            "}\n",
+        ));
+        // sample_table's more(&["POST", "GET"]) forks right at the root
+        assert!(sample_table().to_dot().contains("    n1 [shape=point];\n    n0 -> n1 [arrowhead=none];\n"));
+    }
+}
@@ -0,0 +1,303 @@
+use std::borrow::Cow;
+use std::fmt::Debug;
+use super::{RoutingTable, OwnedRoutingTable, Entity, RegistrationError, RegistrationErrorKind, Constraint, Segment, OneOrMore, SerialOrParallel, parse_segment, parallel_to_one_or_more, to_owned_key};
+
+// Registers every route under the same prefix, so a group of routes only spells it out once
+pub struct Scope<'s, 'a, T: Debug> {
+    rt: &'s mut RoutingTable<'a, T>,
+    prefix: Vec<OneOrMore<'a>>,
+}
+
+impl<'a, T: Debug> RoutingTable<'a, T> {
+
+    // Grafts every route of sub under prefix. Routes already under prefix stay where they are, so
+    // several tables can share a mount point as long as their routes don't collide. Sub's root data
+    // becomes the mount point's fallback, unless the mount point already falls back to that entity
+    // or sub has a fallback of its own (see set_fallback), which then takes its place. A mount point
+    // that already has a fallback rejects a sub table that brings another one
+    pub fn mount(self: &mut Self, prefix: &[&'a str], sub: RoutingTable<'a, T>) -> () {
+        self.try_mount(prefix, sub).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_mount(self: &mut Self, prefix: &[&'a str], sub: RoutingTable<'a, T>) -> Result<(), RegistrationError<'a, T>> {
+        self.try_mount_core(prefix, sub, Cow::Borrowed)
+    }
+
+    pub fn scope<R>(self: &mut Self, prefix: &[&'a str], f: impl FnOnce(&mut Scope<'_, 'a, T>) -> R) -> R {
+        f(&mut Scope { rt: self, prefix: prefix.iter().map(|key| OneOrMore::One(key)).collect() })
+    }
+
+    fn try_mount_core<'r>(self: &mut Self, prefix: &[&'r str], mut sub: RoutingTable<'a, T>, to_key: fn(&'r str) -> Cow<'a, str>) -> Result<(), RegistrationError<'a, T>> {
+        let error = |kind| RegistrationError::new(prefix, &[], kind);
+        let mut constraints = self.constraints.clone();
+        for (kind, predicate) in &sub.constraints {
+            constraints.entry(kind.clone()).or_insert(*predicate);
+        }
+        for key in prefix {
+            match parse_segment(key) {
+                Segment::Literal("") if !self.slash_policy.keeps_empty(false) => return Err(error(RegistrationErrorKind::EmptySegment)),
+                Segment::CatchAll(_) => return Err(error(RegistrationErrorKind::CatchAllNotLast)),
+                Segment::Typed(_, kind) if Constraint::resolve(kind, &constraints).is_none() => return Err(error(RegistrationErrorKind::UnknownConstraint(kind.to_string()))),
+                _ => {}
+            }
+        }
+        if !sub.empty_keys_kept(self.slash_policy) {
+            return Err(error(RegistrationErrorKind::EmptySegment))
+        }
+        // inherited ends up as what the mount point gets from above it, its own fallback left out
+        let mut mount_point = Some(&*self);
+        let mut inherited = self.data.clone();
+        for key in prefix {
+            mount_point = match mount_point {
+                Some(current_rt) => {
+                    inherited = current_rt.inheritable();
+                    current_rt.child(key).map_err(error)?
+                }
+                None => None,
+            };
+        }
+        sub.explicit = false;
+        let existing_fallback = mount_point.and_then(|found_rt| found_rt.fallback.as_ref());
+        let same_as = |entity: &Entity<'a, T>| std::ptr::eq::<T>(&**entity, &*sub.data);
+        if sub.fallback.is_none() && !same_as(&inherited) && !existing_fallback.map_or(false, same_as) {
+            sub.fallback = Some(sub.data.clone());
+        }
+        if let Some(mount_point) = mount_point {
+            mount_point.check_merge(&sub).map_err(error)?;
+        }
+        if let Some(name) = sub.names.keys().find(|name| self.names.contains_key(*name)) {
+            return Err(error(RegistrationErrorKind::DuplicateName(name.to_string())))
+        }
+
+        for (name, template) in std::mem::take(&mut sub.names) {
+            let full_template = prefix.iter().map(|key| vec![to_key(key)]).chain(template).collect();
+            self.names.insert(name, full_template);
+        }
+        self.constraints = constraints.clone();
+        let mut mount_point = self;
+        for key in prefix {
+            if mount_point.child_mut(key).is_none() {
+                let implicit_layer = RoutingTable::new_core(mount_point.inheritable(), false, mount_point.depth+1);
+                mount_point.insert_child(key, implicit_layer, &constraints, to_key);
+            }
+            mount_point = mount_point.child_mut(key).unwrap();
+        }
+        mount_point.merge_core(sub);
+        Ok(())
+    }
+
+    fn check_merge(self: &Self, other: &RoutingTable<'a, T>) -> Result<(), RegistrationErrorKind<'a, T>> {
+        if self.explicit && other.explicit {
+            return Err(RegistrationErrorKind::Duplicate(self.data.clone()))
+        }
+        if let (Some(existing), Some(_)) = (&self.fallback, &other.fallback) {
+            return Err(RegistrationErrorKind::Duplicate(existing.clone()))
+        }
+        if let Some(existing) = other.methods.iter().find_map(|(method, _)| self.registered(Some(method))) {
+            return Err(RegistrationErrorKind::Duplicate(existing.clone()))
+        }
+        for (key, child) in &other.map {
+            if let Some(existing) = self.map.get(key) { existing.check_merge(child)? }
+        }
+        for (name, constraint, child) in &other.typed {
+            if let Some((existing_name, _, existing)) = self.typed.iter().find(|(_, existing, _)| existing.kind() == constraint.kind()) {
+                if existing_name != name { return Err(RegistrationErrorKind::ConflictingNames(existing_name.to_string(), name.to_string())) }
+                existing.check_merge(child)?;
+            }
+        }
+        for (existing, other) in [(&self.param, &other.param), (&self.catch_all, &other.catch_all)] {
+            if let (Some((existing_name, existing)), Some((name, child))) = (existing, other) {
+                if existing_name != name { return Err(RegistrationErrorKind::ConflictingNames(existing_name.to_string(), name.to_string())) }
+                existing.check_merge(child)?;
+            }
+        }
+        Ok(())
+    }
+
+    // other sits at the same place as self, check_merge has to pass first
+    fn merge_core(self: &mut Self, other: RoutingTable<'a, T>) -> () {
+        let RoutingTable { map, typed, param, catch_all, data, explicit, fallback, methods, fan_outs, .. } = other;
+        if fallback.is_some() {
+            self.fallback = fallback;
+            let data = if self.explicit { self.data.clone() } else { self.inheritable() };
+            self.set_data(data);
+        }
+        if explicit {
+            self.explicit = true;
+            self.set_data(data);
+        }
+        self.methods.extend(methods);
+        for fan_out in fan_outs { self.add_fan_out(fan_out) }
+        for (key, child) in map {
+            match self.map.get_mut(&key) {
+                Some(existing) => existing.merge_core(child),
+                None => {
+                    let child = self.adopt(child);
+                    self.map.insert(key, child);
+                }
+            }
+        }
+        for (name, constraint, child) in typed {
+            match self.typed.iter_mut().find(|(_, existing, _)| existing.kind() == constraint.kind()) {
+                Some((_, _, existing)) => existing.merge_core(child),
+                None => {
+                    let child = self.adopt(child);
+                    let position = self.typed.iter().position(|(_, existing, _)| existing.rank() > constraint.rank()).unwrap_or(self.typed.len());
+                    self.typed.insert(position, (name, constraint, child));
+                }
+            }
+        }
+        if let Some((name, child)) = param {
+            match &mut self.param {
+                Some((_, existing)) => existing.merge_core(*child),
+                None => self.param = Some((name, Box::new(self.adopt(*child)))),
+            }
+        }
+        if let Some((name, child)) = catch_all {
+            match &mut self.catch_all {
+                Some((_, existing)) => existing.merge_core(*child),
+                None => self.catch_all = Some((name, Box::new(self.adopt(*child)))),
+            }
+        }
+    }
+
+    // A subtree moved under self takes the depth of its new position, and its implicit layers the data they inherit from self
+    fn adopt(self: &Self, mut child: RoutingTable<'a, T>) -> RoutingTable<'a, T> {
+        child.set_depth(self.depth+1);
+        child.inherit(self.inheritable());
+        child
+    }
+
+    fn set_depth(self: &mut Self, depth: usize) -> () {
+        self.depth = depth;
+        for child in self.children_mut() { child.set_depth(depth+1) }
+    }
+}
+
+impl<T: Debug + 'static> OwnedRoutingTable<T> {
+
+    pub fn mount(self: &mut Self, prefix: &[&str], sub: OwnedRoutingTable<T>) -> () {
+        self.try_mount(prefix, sub).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    pub fn try_mount(self: &mut Self, prefix: &[&str], sub: OwnedRoutingTable<T>) -> Result<(), RegistrationError<'static, T>> {
+        self.0.try_mount_core(prefix, sub.0, to_owned_key)
+    }
+}
+
+impl<'s, 'a, T: Debug> Scope<'s, 'a, T> {
+
+    pub fn scope<R>(self: &mut Self, prefix: &[&'a str], f: impl FnOnce(&mut Scope<'_, 'a, T>) -> R) -> R {
+        let mut full_prefix = self.prefix.clone();
+        full_prefix.extend(prefix.iter().map(|key| OneOrMore::One(key)));
+        f(&mut Scope { rt: self.rt, prefix: full_prefix })
+    }
+
+    pub fn register(self: &mut Self, entity: &'a T, route: &[&'a str]) -> () {
+        self.rt.reg_more(entity, &self.full_route(&route.iter().map(|key| OneOrMore::One(key)).collect::<Vec<OneOrMore>>()))
+    }
+
+    pub fn reg_more(self: &mut Self, entity: &'a T, route: &[OneOrMore<'a>]) -> () {
+        self.rt.reg_more(entity, &self.full_route(route))
+    }
+
+    pub fn reg_parallel(self: &mut Self, entity: &'a T, route: &[SerialOrParallel<'a>]) -> () {
+        self.rt.reg_more(entity, &self.full_route(&parallel_to_one_or_more(route)))
+    }
+
+    pub fn reg_method(self: &mut Self, method: &'a str, entity: &'a T, route: &[OneOrMore<'a>]) -> () {
+        self.rt.reg_method(method, entity, &self.full_route(route))
+    }
+
+    pub fn reg_named(self: &mut Self, name: &'a str, entity: &'a T, route: &[OneOrMore<'a>]) -> () {
+        self.rt.reg_named(name, entity, &self.full_route(route))
+    }
+
+    fn full_route(self: &Self, route: &[OneOrMore<'a>]) -> Vec<OneOrMore<'a>> {
+        self.prefix.iter().chain(route).copied().collect()
+    }
+}
+
+mod test {
+
+    use super::super::{RoutingTable, OwnedRoutingTable, RegistrationErrorKind, MatchKind, MethodMatch, ParamValue, one, par, ser};
+
+    const BOTTOM_FALLBACK: &i32 = &14;
+    const APP_API_V4_SIGNUP: &i32 = &15;
+    const APP_API_V4_SIGNIN: &i32 = &16;
+    const APP_API_V4_SIGNOUT: &i32 = &17;
+    const APP_USERS_ME: &i32 = &18;
+    const APP_INVOICES_ID: &i32 = &19;
+    const AUTH_FALLBACK: &i32 = &30;
+    const BILLING_FALLBACK: &i32 = &31;
+
+    #[test]
+    fn mount_sub_tables() {
+        let mut auth = RoutingTable::new(AUTH_FALLBACK);
+        auth.register(APP_API_V4_SIGNUP, &["auth", "sign-up"]);
+        auth.reg_named("auth.sign_in", APP_API_V4_SIGNIN, &[one("auth"), one("sign-in")]);
+        let mut billing = RoutingTable::new(BOTTOM_FALLBACK);
+        billing.register(APP_INVOICES_ID, &["invoices", "{id:u64}"]);
+
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_USERS_ME, &["api", "v4", "users", "me"]);
+        rt.mount(&["api", "v4"], auth);
+        rt.mount(&["api", "v4"], billing);
+
+        let found = rt.lookup(&["api", "v4", "auth", "sign-up"]).unwrap();
+        assert_eq!(found.val, APP_API_V4_SIGNUP);
+        assert_eq!((found.depth, found.keys_used, found.match_kind()), (4, 4, MatchKind::Exact));
+        assert_eq!(found.keep_going.lookup::<&str>(&[]).unwrap().val, APP_API_V4_SIGNUP);
+
+        let found = rt.lookup(&["api", "v4", "invoices", "7", "pdf"]).unwrap();
+        assert_eq!(found.val, APP_INVOICES_ID);
+        assert_eq!(found.typed("id"), Some(ParamValue::U64(7)));
+        assert_eq!((found.depth, found.keys_used, found.match_kind()), (4, 4, MatchKind::Prefix));
+
+        // auth's root data now covers the mount point, billing was built on the same fallback as rt so it brings none
+        let found = rt.lookup(&["api", "v4", "auth"]).unwrap();
+        assert_eq!(found.val, AUTH_FALLBACK);
+        assert_eq!((found.depth, found.keys_used, found.match_kind()), (3, 3, MatchKind::Fallback));
+        assert_eq!(rt.lookup(&["api", "v4", "DNE"]).unwrap().val, AUTH_FALLBACK);
+        assert_eq!(rt.lookup(&["api", "DNE"]).unwrap().val, BOTTOM_FALLBACK);
+
+        let mut invoices = RoutingTable::new(BILLING_FALLBACK);
+        invoices.register(APP_INVOICES_ID, &["invoices", "new"]);
+        let error = rt.try_mount(&["api", "v4"], invoices).unwrap_err();
+        assert!(matches!(&error.kind, RegistrationErrorKind::Duplicate(existing) if **existing == *AUTH_FALLBACK));
+
+        assert_eq!(rt.lookup(&["api", "v4", "users", "me"]).unwrap().val, APP_USERS_ME);
+        assert_eq!(rt.url_for("auth.sign_in", &[]).unwrap(), "/api/v4/auth/sign-in");
+
+        let mut clashing = RoutingTable::new(AUTH_FALLBACK);
+        clashing.register(APP_API_V4_SIGNOUT, &["auth", "sign-out"]);
+        clashing.register(APP_API_V4_SIGNOUT, &["users", "me"]);
+        let error = rt.try_mount(&["api", "v4"], clashing).unwrap_err();
+        assert!(matches!(&error.kind, RegistrationErrorKind::Duplicate(existing) if **existing == *APP_USERS_ME));
+        assert_eq!(rt.lookup(&["api", "v4", "auth", "sign-out"]).unwrap().val, AUTH_FALLBACK);
+
+        let mut rt_owned = OwnedRoutingTable::new(14);
+        let mut sub_owned = OwnedRoutingTable::new(30);
+        sub_owned.register(15, &["sign-up"]);
+        rt_owned.mount(&["users", ":id"], sub_owned);
+        let found = rt_owned.lookup(&["users", "42", "sign-up"]).unwrap();
+        assert_eq!((*found.val(), found.param("id"), found.depth()), (15, Some("42"), 3));
+        assert_eq!(*rt_owned.lookup(&["users", "42", "DNE"]).unwrap().val(), 30);
+    }
+
+    #[test]
+    fn scope_builder() {
+        let gp = par(&["GET", "POST"]);
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.scope(&["api", "v4"], |s| {
+            s.register(APP_API_V4_SIGNUP, &["sign-up"]);
+            s.reg_parallel(APP_API_V4_SIGNIN, &[ser(&["sign-in"]), gp]);
+            s.scope(&["users"], |s| s.reg_method("GET", APP_USERS_ME, &[one("me")]));
+        });
+
+        assert_eq!(rt.lookup(&["api", "v4", "sign-up"]).unwrap().val, APP_API_V4_SIGNUP);
+        assert_eq!(rt.lookup(&["api", "v4", "sign-in", "POST"]).unwrap().val, APP_API_V4_SIGNIN);
+        assert_eq!(rt.lookup_method("GET", &["api", "v4", "users", "me"]).unwrap().1, MethodMatch::Handler(APP_USERS_ME));
+        assert_eq!(rt.lookup(&["sign-up"]).unwrap().val, BOTTOM_FALLBACK);
+    }
+}
@@ -0,0 +1,209 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use super::{RoutingTable, RTLookupResult, Constraint, ParamValue, MatchKind};
+
+// Read-only copy of a RoutingTable where every chain of single-child levels is merged into one edge,
+// so a lookup hashes once per chain instead of once per segment and the levels inside a chain take
+// no node of their own. It is built once and owns everything, the table can be dropped afterwards.
+// Typed, param and catch-all children are tried in the RoutingTable's order, so the results are
+// exactly the table's
+pub struct RadixRoutingTable<'a, T: Debug> {
+    root: RadixNode<'a, T>,
+    implicit: Vec<RoutingTable<'a, T>>,
+}
+
+// The leaf is the node's own copy of the table node, whose data, fallback and methods lookups are answered from
+struct RadixNode<'a, T: Debug> {
+    leaf: RoutingTable<'a, T>,
+    edges: HashMap<Cow<'a, str>, RadixEdge<'a, T>>,
+    typed: Vec<(Cow<'a, str>, Constraint<'a>, RadixNode<'a, T>)>,
+    param: Option<(Cow<'a, str>, Box<RadixNode<'a, T>>)>,
+    catch_all: Option<(Cow<'a, str>, Box<RadixNode<'a, T>>)>,
+}
+
+// label holds the keys after the edge's own one. The levels they pass through are implicit layers
+// without methods or a fallback that carry the same data, so a lookup stopping among them is answered
+// by implicit[inner], which every edge through levels with that data shares
+struct RadixEdge<'a, T: Debug> {
+    label: Box<[Cow<'a, str>]>,
+    inner: u32,
+    target: RadixNode<'a, T>,
+}
+
+impl<'a, T: Debug> RadixRoutingTable<'a, T> {
+
+    pub fn new(rt: &RoutingTable<'a, T>) -> Self {
+        let mut implicit = Vec::new();
+        let root = RadixNode::new(rt, &mut implicit);
+        RadixRoutingTable { root, implicit }
+    }
+
+    pub fn lookup<'s, S: AsRef<str>>(self: &'s Self, keys: &'s [S]) -> Option<RTLookupResult<'s, T, S>> {
+        let found = self.lookup_core(&self.root, keys, 0, &mut Vec::new())?;
+        self.root.leaf.record_hit(&found);
+        Some(found)
+    }
+
+    // Same search as RoutingTable::lookup_core, an edge counts as the one static child
+    fn lookup_core<'s, S: AsRef<str>>(self: &'s Self, node: &'s RadixNode<'a, T>, keys: &'s [S], start: usize, params: &mut Vec<(&'s str, &'s str, ParamValue)>) -> Option<RTLookupResult<'s, T, S>> {
+        let key = match keys.get(start) {
+            Some(key) => key.as_ref(),
+            None => return Some(node.leaf.stop_here(keys, start, params)),
+        };
+        let mut best: Option<RTLookupResult<'s, T, S>> = None;
+        if let Some(edge) = node.edges.get(key) {
+            let matched = edge.label.iter().zip(&keys[start+1..]).take_while(|(label, key)| *label == key.as_ref()).count();
+            let found = if matched < edge.label.len() {
+                let mut found = self.implicit[edge.inner as usize].stop_here(keys, start+1+matched, params);
+                found.depth = node.leaf.depth + 1 + matched;
+                found
+            } else {
+                self.lookup_core(&edge.target, keys, start+1+matched, params)?
+            };
+            if found.match_kind == MatchKind::Exact { return Some(found) }
+            best = Some(found);
+        }
+        let candidates = node.typed.iter().filter_map(|(name, constraint, typed_node)| constraint.test(key).map(|value| (typed_node, (&**name, key, value))))
+            .chain(node.param.iter().map(|(name, param_node)| (&**param_node, (&**name, key, ParamValue::Str))));
+        let params_len = params.len();
+        for (child, param) in candidates {
+            params.push(param);
+            let found = self.lookup_core(child, keys, start+1, params)?;
+            if found.match_kind == MatchKind::Exact { return Some(found) }
+            params.truncate(params_len);
+            if best.as_ref().map_or(true, |best| found.match_kind.rank() < best.match_kind.rank()) { best = Some(found) }
+        }
+        if let Some((_, catch_all_node)) = &node.catch_all {
+            let found = catch_all_node.leaf.catch_all_here(keys, start, params);
+            if best.as_ref().map_or(true, |best| found.match_kind.rank() < best.match_kind.rank()) { best = Some(found) }
+        }
+        Some(best.unwrap_or_else(|| node.leaf.stop_here(keys, start, params)))
+    }
+}
+
+impl<'a, T: Debug> RadixNode<'a, T> {
+
+    fn new(source: &RoutingTable<'a, T>, implicit: &mut Vec<RoutingTable<'a, T>>) -> Self {
+        let edges = source.map.iter().map(|(key, child)| {
+            let mut label = Vec::new();
+            let mut tail = child;
+            let mergeable = |rt: &RoutingTable<'a, T>| !rt.explicit && rt.methods.is_empty() && rt.fallback.is_none()
+                && rt.map.len() == 1 && !rt.has_dynamic_children() && std::ptr::eq::<T>(&*rt.data, &*child.data);
+            while mergeable(tail) {
+                let (next_key, next_child) = tail.map.iter().next().unwrap();
+                label.push(next_key.clone());
+                tail = next_child;
+            }
+            let shared = implicit.iter().position(|inner_rt| std::ptr::eq::<T>(&*inner_rt.data, &*child.data));
+            let inner = match shared {
+                _ if label.len() == 0 => 0,
+                Some(inner) => inner,
+                None => {
+                    implicit.push(child.leaf());
+                    implicit.len() - 1
+                }
+            };
+            (key.clone(), RadixEdge { label: label.into(), inner: inner as u32, target: RadixNode::new(tail, implicit) })
+        }).collect();
+        RadixNode {
+            leaf: source.leaf(),
+            edges,
+            typed: source.typed.iter().map(|(name, constraint, typed_rt)| (name.clone(), constraint.clone(), RadixNode::new(typed_rt, implicit))).collect(),
+            param: source.param.as_ref().map(|(name, param_rt)| (name.clone(), Box::new(RadixNode::new(param_rt, implicit)))),
+            catch_all: source.catch_all.as_ref().map(|(name, catch_all_rt)| (name.clone(), Box::new(RadixNode::new(catch_all_rt, implicit)))),
+        }
+    }
+}
+
+mod test {
+
+    use std::borrow::Cow;
+    use std::fmt::Debug;
+    use std::mem::size_of;
+    use test::Bencher;
+    use super::{RadixRoutingTable, RadixNode, RadixEdge};
+    use super::super::{RoutingTable, Constraint, Entity};
+    use super::super::index_test::{assert_same_results, generated_table, bench_lookups, table_bytes, key_bytes};
+
+    const BOTTOM_FALLBACK: &i32 = &14;
+    const APP_API_V4_SIGNUP: &i32 = &15;
+    const APP_API_V4_SIGNIN: &i32 = &16;
+    const APP_API_V4_USERS_ID: &i32 = &17;
+    const APP_DEEP: &i32 = &18;
+    const APP_STATIC: &i32 = &19;
+    const APP_A_FALLBACK: &i32 = &20;
+
+    #[test]
+    fn same_results_as_hashmap_tree() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNUP  , &["api", "v4", "sign-up"        ]);
+        rt.register(APP_API_V4_SIGNIN  , &["api", "v4", "sign-in"        ]);
+        rt.register(APP_API_V4_USERS_ID, &["api", "v4", "users", ":id"   ]);
+        rt.register(APP_DEEP           , &["a", "b", "c", "d", "e"       ]);
+        rt.register(APP_STATIC         , &["a", "b", "c", "*path"        ]);
+        rt.register(APP_DEEP           , &["x", "y", "z"                 ]);
+        rt.set_fallback(&["x", "y"], APP_A_FALLBACK);
+        let radix = RadixRoutingTable::new(&rt);
+
+        let edge = radix.root.edges.get("api").unwrap();
+        assert_eq!(&*edge.label, &["v4"]);
+        assert_eq!(edge.target.edges.len(), 3);
+        assert!(edge.target.edges.get("users").unwrap().target.param.is_some());
+        assert_eq!(radix.implicit.len(), 1);
+
+        let keys: &[&[&str]] = &[
+            &[], &["api"], &["api", "v4"], &["api", "v4", "sign-up"], &["api", "v4", "sign-in", "tail"],
+            &["api", "v4", "DNE"], &["api", "v5", "sign-up"], &["api", "v4", "users", "42"], &["api", "v4", "users"],
+            &["api", "v4", "users", "42", "tail"], &["a", "b"], &["a", "b", "c"], &["a", "b", "c", "d"], &["a", "b", "c", "d", "e"],
+            &["a", "b", "c", "x", "y"], &["x"], &["x", "y"], &["x", "y", "DNE"], &["x", "y", "z"], &["DNE"],
+        ];
+        assert_same_results(&rt, keys, |keys| radix.lookup(keys));
+    }
+
+    // Same estimate as table_bytes, plus the implicit nodes that the merged levels share
+    fn radix_bytes<T: Debug>(node: &RadixNode<T>) -> usize {
+        node.edges.capacity() * (size_of::<(Cow<str>, RadixEdge<T>)>() + 1)
+            + node.edges.iter().map(|(key, edge)| {
+                key_bytes(key) + edge.label.len() * size_of::<Cow<str>>() + edge.label.iter().map(key_bytes).sum::<usize>() + radix_bytes(&edge.target)
+            }).sum::<usize>()
+            + node.typed.capacity() * size_of::<(Cow<str>, Constraint, RadixNode<T>)>()
+            + node.typed.iter().map(|(name, _, typed_node)| key_bytes(name) + radix_bytes(typed_node)).sum::<usize>()
+            + node.param.iter().chain(&node.catch_all).map(|(name, child)| size_of::<RadixNode<T>>() + key_bytes(name) + radix_bytes(child)).sum::<usize>()
+            + node.leaf.methods.capacity() * size_of::<(Cow<str>, Entity<T>)>()
+    }
+
+    // Every route is five segments long and every item level has exactly one child,
+    // which is the kind of chain that the radix table merges
+    fn route_of(i: usize) -> Vec<String> {
+        vec![String::from("api"), format!("v{}", i % 10), format!("group{}", i / 100), format!("item{}", i), String::from("detail")]
+    }
+
+    #[test]
+    fn smaller_than_hashmap_tree() {
+        let (rt, _) = generated_table(10_000, route_of);
+        let radix = RadixRoutingTable::new(&rt);
+        let radix_total = radix_bytes(&radix.root) + radix.implicit.capacity() * size_of::<RoutingTable<usize>>();
+        assert!(radix_total < table_bytes(&rt), "{} >= {}", radix_total, table_bytes(&rt));
+    }
+
+    fn bench_radix(b: &mut Bencher, routes: usize, radix: bool) {
+        let (rt, samples) = generated_table(routes, route_of);
+        let rt_radix = RadixRoutingTable::new(&rt);
+        bench_lookups(b, &samples, |keys| if radix { rt_radix.lookup(keys) } else { rt.lookup(keys) }.map(|found| *found.val));
+    }
+
+    // Estimated memory (table_bytes and radix_bytes): 10k routes take 17.2 MB as a HashMap tree and
+    // 8.5 MB as a radix table, 100k routes take 174.2 MB and 88.8 MB
+    #[bench]
+    fn hashmap_tree_10k(b: &mut Bencher) { bench_radix(b, 10_000, false) }
+
+    #[bench]
+    fn radix_10k(b: &mut Bencher) { bench_radix(b, 10_000, true) }
+
+    #[bench]
+    fn hashmap_tree_100k(b: &mut Bencher) { bench_radix(b, 100_000, false) }
+
+    #[bench]
+    fn radix_100k(b: &mut Bencher) { bench_radix(b, 100_000, true) }
+}
@@ -0,0 +1,228 @@
+use std::fmt::Debug;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use std::time::Duration;
+use serde_json::{json, Value};
+use super::{RoutingTable, OwnedRoutingTable, RTLookupResult, MatchKind};
+
+// Bucket i counts latencies below 2^i microseconds that didn't fit bucket i-1, the last one everything above
+const LATENCY_BUCKETS: usize = 24;
+
+// Counters only ever go up (until reset_stats) and are read with Relaxed loads, so an export taken
+// while lookups are running is a close approximation rather than one consistent snapshot
+#[derive(Debug, Default)]
+pub struct NodeStats {
+    hits: AtomicU64,
+    fallback_hits: AtomicU64,
+    // Null until the first latency comes in, then set once and freed with the node
+    latency: AtomicPtr<LatencyHistogram>,
+}
+
+#[derive(Debug, Default)]
+struct LatencyHistogram {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl NodeStats {
+    // Exact and Prefix matches
+    pub fn hits(self: &Self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn fallback_hits(self: &Self) -> u64 {
+        self.fallback_hits.load(Ordering::Relaxed)
+    }
+
+    // How many latencies were recorded and their sum
+    pub fn latency(self: &Self) -> Option<(u64, Duration)> {
+        let histogram = self.histogram()?;
+        Some((histogram.count.load(Ordering::Relaxed), Duration::from_micros(histogram.total_micros.load(Ordering::Relaxed))))
+    }
+
+    fn hit(self: &Self, match_kind: MatchKind) -> () {
+        let counter = if match_kind == MatchKind::Fallback { &self.fallback_hits } else { &self.hits };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn histogram(self: &Self) -> Option<&LatencyHistogram> {
+        // Only ever null or a histogram from record_latency, which lives as long as self
+        unsafe { self.latency.load(Ordering::Acquire).as_ref() }
+    }
+
+    // The histogram is only allocated for nodes whose handlers actually report a latency. When two
+    // threads race to allocate it, the one that loses frees its own and records into the winner's
+    fn record_latency(self: &Self, elapsed: Duration) -> () {
+        let histogram = match self.histogram() {
+            Some(histogram) => histogram,
+            None => {
+                let allocated = Box::into_raw(Box::new(LatencyHistogram::default()));
+                match self.latency.compare_exchange(ptr::null_mut(), allocated, Ordering::AcqRel, Ordering::Acquire) {
+                    Ok(_) => unsafe { &*allocated },
+                    Err(existing) => {
+                        drop(unsafe { Box::from_raw(allocated) });
+                        unsafe { &*existing }
+                    }
+                }
+            }
+        };
+        let micros = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = ((u64::BITS - micros.leading_zeros()) as usize).min(LATENCY_BUCKETS-1);
+        histogram.count.fetch_add(1, Ordering::Relaxed);
+        histogram.total_micros.fetch_add(micros, Ordering::Relaxed);
+        histogram.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn reset(self: &Self) -> () {
+        self.hits.store(0, Ordering::Relaxed);
+        self.fallback_hits.store(0, Ordering::Relaxed);
+        if let Some(histogram) = self.histogram() {
+            histogram.count.store(0, Ordering::Relaxed);
+            histogram.total_micros.store(0, Ordering::Relaxed);
+            for bucket in &histogram.buckets { bucket.store(0, Ordering::Relaxed) }
+        }
+    }
+
+    // Buckets are [upper bound in microseconds, count], empty ones are left out and the last bound is null
+    fn to_json(self: &Self) -> Value {
+        let latency = self.histogram().map(|histogram| json!({
+            "count": histogram.count.load(Ordering::Relaxed),
+            "total_us": histogram.total_micros.load(Ordering::Relaxed),
+            "buckets": histogram.buckets.iter().enumerate()
+                .map(|(bucket, count)| (bucket, count.load(Ordering::Relaxed)))
+                .filter(|(_, count)| *count > 0)
+                .map(|(bucket, count)| json!([if bucket+1 < LATENCY_BUCKETS { Some(1u64 << bucket) } else { None }, count]))
+                .collect::<Vec<Value>>(),
+        }));
+        json!({
+            "hits": self.hits(),
+            "fallback_hits": self.fallback_hits(),
+            "latency": latency,
+        })
+    }
+}
+
+impl Drop for NodeStats {
+    fn drop(self: &mut Self) -> () {
+        let histogram = *self.latency.get_mut();
+        if !histogram.is_null() { drop(unsafe { Box::from_raw(histogram) }) }
+    }
+}
+
+impl<'a, T: Debug> RoutingTable<'a, T> {
+
+    // Off by default, every lookup then counts a hit or a fallback hit on the node it ended at.
+    // Stats belong to one table, a table swapped into a SharedRouter starts from zero
+    pub fn record_stats(self: &mut Self, enabled: bool) -> () {
+        self.record_stats = enabled;
+    }
+
+    pub fn reset_stats(self: &Self) -> () {
+        self.stats.reset();
+        for child in self.children() { child.reset_stats() }
+    }
+
+    // Every node depth first, implicit layers included since fallback hits land on them.
+    // Routes that are registered but have no hits are the dead ones
+    pub fn stats_json(self: &Self) -> Value {
+        let mut exported = Vec::new();
+        self.stats_json_core("", &mut exported);
+        Value::from(exported)
+    }
+
+    fn stats_json_core(self: &Self, route: &str, exported: &mut Vec<Value>) -> () {
+        let mut node = self.stats.to_json();
+        node["route"] = Value::from(if route.len() == 0 { "/" } else { route });
+        node["explicit"] = Value::from(self.explicit);
+        exported.push(node);
+        for (key, child) in self.labeled_children() {
+            child.stats_json_core(&format!("{}/{}", route, key), exported);
+        }
+    }
+
+    pub(super) fn record_hit<S>(self: &Self, found: &RTLookupResult<'_, T, S>) -> () {
+        if self.record_stats { found.keep_going.stats.hit(found.match_kind) }
+    }
+}
+
+impl<T: Debug + 'static> OwnedRoutingTable<T> {
+
+    pub fn record_stats(self: &mut Self, enabled: bool) -> () {
+        self.0.record_stats(enabled)
+    }
+}
+
+impl<'a, T: Debug, S> RTLookupResult<'a, T, S> {
+
+    pub fn stats(self: &Self) -> &'a NodeStats {
+        &self.keep_going.stats
+    }
+
+    // For the caller to report how long the handler it found took, recorded whether or not the table records hits
+    pub fn record_latency(self: &Self, elapsed: Duration) -> () {
+        self.keep_going.stats.record_latency(elapsed)
+    }
+}
+
+mod test {
+
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use serde_json::json;
+    use super::super::{RoutingTable, OwnedRoutingTable};
+
+    const BOTTOM_FALLBACK: &i32 = &14;
+    const APP_API_V4_SIGNUP: &i32 = &15;
+    const APP_API_V4_SIGNIN: &i32 = &16;
+    const APP_USERS_ID: &i32 = &19;
+
+    #[test]
+    fn hits_and_latency() {
+        let mut rt = RoutingTable::new(BOTTOM_FALLBACK);
+        rt.register(APP_API_V4_SIGNUP, &["api", "v4", "sign-up"]);
+        rt.register(APP_API_V4_SIGNIN, &["api", "v4", "sign-in"]);
+        rt.register(APP_USERS_ID     , &["users", ":id"]);
+        rt.lookup(&["api", "v4", "sign-up"]);
+        assert_eq!(rt.lookup(&["api", "v4", "sign-up"]).unwrap().stats().hits(), 0);
+
+        rt.record_stats(true);
+        for _ in 0..3 { rt.lookup(&["api", "v4", "sign-up"]); }
+        rt.lookup(&["api", "v4", "DNE"]);
+        rt.lookup_method("GET", &["users", "42"]);
+        let found = rt.lookup(&["users", "7"]).unwrap();
+        found.record_latency(Duration::from_micros(0));
+        found.record_latency(Duration::from_micros(5));
+        found.record_latency(Duration::from_micros(6));
+        found.record_latency(Duration::from_secs(3600));
+        assert_eq!(found.stats().latency(), Some((4, Duration::from_micros(3600_000_011))));
+
+        let exported = rt.stats_json();
+        let node = |route: &str| exported.as_array().unwrap().iter().find(|node| node["route"] == json!(route)).unwrap().clone();
+        assert_eq!(node("/"), json!({ "route": "/", "explicit": true, "hits": 0, "fallback_hits": 0, "latency": null }));
+        assert_eq!(node("/api/v4"), json!({ "route": "/api/v4", "explicit": false, "hits": 0, "fallback_hits": 1, "latency": null }));
+        assert_eq!(node("/api/v4/sign-up")["hits"], json!(3));
+        assert_eq!(node("/api/v4/sign-in")["hits"], json!(0));
+        assert_eq!(node("/users/:id"), json!({
+            "route": "/users/:id", "explicit": true, "hits": 2, "fallback_hits": 0,
+            "latency": { "count": 4, "total_us": 3600_000_011u64, "buckets": [[1, 1], [8, 2], [null, 1]] },
+        }));
+        assert_eq!(exported.as_array().unwrap().len(), 7);
+
+        rt.reset_stats();
+        assert_eq!(rt.stats_json()[4], json!({ "route": "/api/v4/sign-up", "explicit": true, "hits": 0, "fallback_hits": 0, "latency": null }));
+        assert_eq!(rt.lookup(&["users", "7"]).unwrap().stats().latency(), Some((0, Duration::from_micros(0))));
+
+        // Threads racing to record a node's first latency all end up in the same histogram
+        let mut rt_owned = OwnedRoutingTable::new(14);
+        rt_owned.register(19, &["users", ":id"]);
+        let rt_owned = Arc::new(rt_owned);
+        let threads = (0..8).map(|_| {
+            let rt_owned = Arc::clone(&rt_owned);
+            thread::spawn(move || for _ in 0..100 { rt_owned.lookup(&["users", "7"]).unwrap().record_latency(Duration::from_micros(2)) })
+        }).collect::<Vec<_>>();
+        for thread in threads { thread.join().unwrap() }
+        assert_eq!(rt_owned.lookup(&["users", "7"]).unwrap().stats().latency(), Some((800, Duration::from_micros(1600))));
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use arc_swap::ArcSwap;
+use super::routing_table::{OwnedRoutingTable, RTLookupResult, MethodMatch};
+use super::parse_url::SlashPolicy;
+
+// Readers never take a lock: every lookup pins the table it started on, so a lookup in flight
+// keeps reading the old table until it finishes, while writers build a new table and swap it in.
+// The old table is dropped once the last reader holding it lets go.
+pub struct SharedRouter<T: Debug + 'static> {
+    current: Arc<ArcSwap<OwnedRoutingTable<T>>>,
+}
+
+impl<T: Debug + 'static> SharedRouter<T> {
+
+    pub fn new(rt: OwnedRoutingTable<T>) -> Self {
+        SharedRouter { current: Arc::new(ArcSwap::from_pointee(rt)) }
+    }
+
+    pub fn lookup<R, S: AsRef<str>>(self: &Self, keys: &[S], f: impl FnOnce(Option<RTLookupResult<T, S>>) -> R) -> R {
+        let rt = self.current.load();
+        f(rt.lookup(keys))
+    }
+
+    pub fn lookup_method<R, S: AsRef<str>>(self: &Self, method: &str, keys: &[S], f: impl FnOnce(Option<(RTLookupResult<T, S>, MethodMatch<T>)>) -> R) -> R {
+        let rt = self.current.load();
+        f(rt.lookup_method(method, keys))
+    }
+
+    // What urls have to be parsed with before their segments are looked up here
+    pub fn slash_policy(self: &Self) -> SlashPolicy {
+        self.current.load().slash_policy()
+    }
+
+    // For readers that need the same table across several lookups
+    pub fn snapshot(self: &Self) -> Arc<OwnedRoutingTable<T>> {
+        self.current.load_full()
+    }
+
+    pub fn swap(self: &Self, rt: OwnedRoutingTable<T>) -> Arc<OwnedRoutingTable<T>> {
+        self.current.swap(Arc::new(rt))
+    }
+}
+
+impl<T: Debug + 'static> Clone for SharedRouter<T> {
+    fn clone(self: &Self) -> Self {
+        SharedRouter { current: Arc::clone(&self.current) }
+    }
+}
+
+mod test {
+
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use super::SharedRouter;
+    use super::super::routing_table::OwnedRoutingTable;
+
+    // Every route of generation n resolves to n, so a reader that ever sees two different
+    // values within one snapshot has observed a half-swapped table
+    fn generation(n: usize) -> OwnedRoutingTable<usize> {
+        let mut rt = OwnedRoutingTable::new(n);
+        rt.register(n, &["api", "v4", "sign-up"]);
+        rt.register(n, &["api", "v4", "sign-in"]);
+        rt.register(n, &["users", ":id"]);
+        rt
+    }
+
+    #[test]
+    fn swap_under_load() {
+        let router = SharedRouter::new(generation(0));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let readers = (0..8).map(|_| {
+            let router = router.clone();
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let mut last_seen = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    let rt = router.snapshot();
+                    let seen = *rt.lookup(&["api", "v4", "sign-up"]).unwrap().val();
+                    assert_eq!(*rt.lookup(&["api", "v4", "sign-in"]).unwrap().val(), seen);
+                    assert_eq!(*rt.lookup(&["users", "42"]).unwrap().val(), seen);
+                    assert_eq!(*rt.lookup(&["DNE"]).unwrap().val(), seen);
+                    assert!(seen >= last_seen);
+                    last_seen = seen;
+                    router.lookup(&["users", "42"], |found| assert_eq!(found.unwrap().param("id"), Some("42")));
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        let mut pinned = router.snapshot();
+        for n in 1..=500 {
+            let replaced = router.swap(generation(n));
+            assert_eq!(*replaced.lookup::<&str>(&[]).unwrap().val(), n-1);
+            if n % 100 == 0 { pinned = router.snapshot() }
+        }
+        stop.store(true, Ordering::Relaxed);
+
+        for reader in readers { reader.join().unwrap(); }
+        assert_eq!(*pinned.lookup(&["users", "42"]).unwrap().val(), 500);
+        assert_eq!(*router.snapshot().lookup(&["api", "v4", "sign-in"]).unwrap().val(), 500);
+    }
+}
@@ -1,19 +1,25 @@
-#![feature(proc_macro_hygiene, decl_macro)]
+#![feature(proc_macro_hygiene, decl_macro, test)]
 #[macro_use] extern crate rocket;
 mod lib;
 extern crate rouille;
+extern crate test;
 use std::thread;
 use std::result::Result;
 use rouille::Response;
-use lib::parse_url::ParsedUrl;
+use lib::parse_url::{ParsedUrl, SlashPolicy};
+use lib::routing_table::{OwnedRoutingTable, MethodMatch};
+use lib::shared_router::SharedRouter;
+use lib::host_router::HostRouter;
+use lib::route_config::{HandlerRegistry, load_routes_file};
 
 
 fn main() {
+    let hosts = hosts();
     let rocket_thread = thread::spawn(move || {
         rocket_main();
     });
     let rouille_thread = thread::spawn(move || {
-        rouille_main();
+        rouille_main(hosts);
     });
     rocket_thread.join().unwrap();
     rouille_thread.join().unwrap();
@@ -27,7 +33,30 @@ fn rocket_main() {
     rocket::custom(conf).mount("/", routes![]).launch();
 }
 
-fn rouille_main() {
+// New routes can be built at any time and put live with router.swap() while rouille keeps serving.
+// Set ROUTES_JSON to a route config file to change routes without recompiling
+fn routes() -> OwnedRoutingTable<&'static str> {
+    let mut registry = HandlerRegistry::new();
+    registry.register("echo", "echo");
+    let mut rt = match std::env::var("ROUTES_JSON") {
+        Ok(path) => load_routes_file(&path, &registry).unwrap_or_else(|error| panic!("{}: {}", path, error)),
+        Err(_) => OwnedRoutingTable::new("echo"),
+    };
+    rt.set_slash_policy(SlashPolicy::Redirect);
+    rt
+}
+
+// Hosts get their own SharedRouter, e.g. hosts.add_host("*.localhost", SharedRouter::new(...)),
+// every other host falls through to the default one
+fn hosts() -> HostRouter<SharedRouter<&'static str>> {
+    let mut hosts = HostRouter::new();
+    hosts.set_default(SharedRouter::new(routes()));
+    hosts
+}
+
+// Set ROUTES_DEV to have 404s list the closest routes next to where matching stopped
+fn rouille_main(hosts: HostRouter<SharedRouter<&'static str>>) {
+    let dev = std::env::var("ROUTES_DEV").is_ok();
     rouille::start_server("0.0.0.0:10099", move |request| {
         println!("{:?}", request);
         let url = &request.raw_url();
@@ -35,9 +64,38 @@ fn rouille_main() {
         let qur = &request.get_param("arg1");
         println!("{}", url);
         println!("{:?}", qur);
-        let parsed_url = parse_url(url);
+        let host = hosts.route(request.header("Host").unwrap_or(""));
+        let parsed_url = parse_url(url, host.as_ref().map_or(SlashPolicy::Redirect, |host| host.router.slash_policy()));
         println!("{:?}", parsed_url);
-        return Response::text(format!("{:#?}", parsed_url));
+        if let Some(location) = parsed_url.as_ref().ok().and_then(|parsed_url| parsed_url.redirect()) {
+            return Response::redirect_308(location.to_string())
+        }
+        let route = parsed_url.as_ref().ok().zip(host).map(|(parsed_url, host)| {
+            let segments = parsed_url.decoded_segments();
+            host.router.lookup_method(request.method(), segments, |found| found.map(|(found, method_match)| match method_match {
+                MethodMatch::Handler(handler) => (200, Some(*handler), None, Vec::new()),
+                MethodMatch::NotAllowed(allowed) => (405, None, Some(allowed.join(", ")), Vec::new()),
+                MethodMatch::Options(allowed) => (204, None, Some(allowed.join(", ")), Vec::new()),
+                MethodMatch::NotFound if dev => {
+                    let suggestions = found.suggestions(3).iter().map(|sibling| {
+                        segments[..found.keys_used()].iter().map(|segment| format!("/{}", segment)).collect::<String>() + "/" + sibling
+                    }).collect::<Vec<String>>();
+                    (404, None, None, suggestions)
+                }
+                MethodMatch::NotFound => (404, None, None, Vec::new()),
+            }))
+        });
+        let response = match &route {
+            Some(Some((404, _, _, suggestions))) if dev && suggestions.len() > 0 => {
+                Response::text(format!("No route for {}\nDid you mean:\n{}\n", url, suggestions.join("\n")))
+            }
+            _ => Response::text(format!("{:#?}\n{:?}", parsed_url, route)),
+        };
+        match route {
+            Some(Some((status, _, Some(allowed), _))) => response.with_status_code(status).with_additional_header("Allow", allowed),
+            Some(Some((status, _, None, _))) => response.with_status_code(status),
+            _ => response,
+        }
     })
 }
 
@@ -45,8 +103,10 @@ fn decode_url(url: &str) -> Result<String, std::str::Utf8Error> {
     Ok(String::from(percent_encoding::percent_decode_str(url).decode_utf8()?))
 }
 
-fn parse_url(raw_url: &str) -> Result<ParsedUrl, std::str::Utf8Error> {
-    ParsedUrl::parse_new(raw_url, decode_url)
+// Urls are parsed the way the host's table expects, routes() sets Redirect so "/api/v4/" and
+// "//api/v4" are answered with a redirect to "/api/v4" and routes never see empty segments
+fn parse_url(raw_url: &str, policy: SlashPolicy) -> Result<ParsedUrl, std::str::Utf8Error> {
+    ParsedUrl::parse_with_policy(raw_url, decode_url, policy)
 }
 
 #[test]